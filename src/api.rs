@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tiny_http::{Header, Request, Response};
+
+use crate::hlds::PlayerInfo;
+
+/// Decoded `A2S_INFO` reply of a server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InfoStatus {
+    pub name: String,
+    pub map: String,
+    pub game: String,
+    pub folder: String,
+    pub version: String,
+    pub players: u8,
+    pub max_players: u8,
+    pub bots: u8,
+    pub vac: bool,
+    pub password: bool,
+}
+
+/// Latest known state of a server returned by `/api/servers`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ServerStatus {
+    pub addr: String,
+    pub up: bool,
+    /// Unix time of the last info reply
+    pub last_update: Option<u64>,
+    pub info: Option<InfoStatus>,
+    /// Only known when players are queried
+    pub players: Option<Vec<PlayerInfo>>,
+}
+
+/// Status of monitored servers served as JSON
+#[derive(Default)]
+pub struct Api {
+    servers: Mutex<HashMap<SocketAddr, ServerStatus>>,
+}
+
+impl Api {
+    /// Changes the status of a server, adding it if it's not known yet
+    pub fn update(&self, addr: SocketAddr, f: impl FnOnce(&mut ServerStatus)) {
+        let Ok(mut servers) = self.servers.lock() else {
+            tracing::debug!("Can't access server status");
+            return;
+        };
+        f(servers.entry(addr).or_insert_with(|| ServerStatus {
+            addr: addr.to_string(),
+            ..ServerStatus::default()
+        }));
+    }
+
+    pub fn rename(&self, old: SocketAddr, new: SocketAddr) {
+        if let Ok(mut servers) = self.servers.lock() {
+            if let Some(status) = servers.remove(&old) {
+                servers.insert(new, status);
+            }
+        }
+    }
+
+    pub fn remove(&self, addr: SocketAddr) {
+        if let Ok(mut servers) = self.servers.lock() {
+            servers.remove(&addr);
+        }
+    }
+
+    /// Answers `/api/servers` with every server and `/api/servers/{addr}`
+    /// with a single one
+    pub fn handle(&self, request: Request) {
+        let url = request.url();
+        let path = url.split_once('?').map_or(url, |(path, _)| path);
+        let addr = path.trim_start_matches("/api/servers").trim_matches('/');
+        let body = if addr.is_empty() {
+            serde_json::to_string(&self.servers())
+        } else if let Some(status) = self.server(addr) {
+            serde_json::to_string(&status)
+        } else {
+            respond(
+                request,
+                Response::from_string("Not found").with_status_code(404),
+            );
+            return;
+        };
+        let response = match body {
+            Ok(body) => Response::from_string(body).with_header(
+                Header::from_bytes("Content-Type", "application/json")
+                    .expect("header should be valid"),
+            ),
+            Err(err) => {
+                Response::from_string(err.to_string()).with_status_code(500)
+            },
+        };
+        respond(request, response);
+    }
+
+    /// Servers ordered by address
+    fn servers(&self) -> Vec<ServerStatus> {
+        let mut servers: Vec<_> = self
+            .servers
+            .lock()
+            .map(|servers| servers.values().cloned().collect())
+            .unwrap_or_default();
+        servers.sort_by(|a, b| a.addr.cmp(&b.addr));
+        servers
+    }
+
+    /// Server by its address or the hostname it was configured with
+    fn server(&self, addr: &str) -> Option<ServerStatus> {
+        let servers = self.servers.lock().ok()?;
+        servers
+            .iter()
+            .find(|(key, status)| {
+                status.addr == addr || key.to_string() == addr
+            })
+            .map(|(_, status)| status.clone())
+    }
+}
+
+fn respond<R: std::io::Read>(request: Request, response: Response<R>) {
+    if let Err(err) = request.respond(response) {
+        tracing::debug!("Can't send response: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::Api;
+
+    #[test]
+    fn find_server_status() {
+        let api = Api::default();
+        let first: SocketAddr = "127.0.0.1:27015".parse().unwrap();
+        let second: SocketAddr = "127.0.0.2:27015".parse().unwrap();
+        api.update(second, |status| {
+            status.addr = "cs.example.com:27015".to_owned();
+            status.up = true;
+        });
+        api.update(first, |status| status.up = false);
+
+        let addrs: Vec<_> = api
+            .servers()
+            .into_iter()
+            .map(|status| status.addr)
+            .collect();
+        assert_eq!(addrs, ["127.0.0.1:27015", "cs.example.com:27015"]);
+        assert!(api.server("cs.example.com:27015").is_some_and(|s| s.up));
+        assert!(api.server("127.0.0.2:27015").is_some_and(|s| s.up));
+
+        api.rename(second, "127.0.0.3:27015".parse().unwrap());
+        assert!(api.server("127.0.0.2:27015").is_none());
+        api.remove(first);
+        assert!(api.server("127.0.0.1:27015").is_none());
+    }
+}
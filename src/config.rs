@@ -1,11 +1,17 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::Duration;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail, Context};
+use clap::builder::ArgPredicate;
 use clap::{Parser, ValueEnum};
-use serde::Serialize;
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::to_value;
 use tracing::level_filters::LevelFilter;
 
+use crate::events::ServerEvent;
+
 /// HLDS metrics exporter in prometheus format
 #[derive(Debug, Parser, Serialize)]
 #[command(author, version, about, long_about = None)]
@@ -22,13 +28,284 @@ pub struct Config {
     #[arg(long, env, value_parser = socketaddr_value_parser, default_value = "127.0.0.1:9000")]
     pub metrics_addr: SocketAddr,
 
-    /// HLDS Server Addresses
-    #[arg(long, env, value_parser = socketaddr_value_parser, num_args = 1.., default_value = "127.0.0.1:27015")]
-    pub server_addr: Vec<SocketAddr>,
+    /// TOML file with per server configuration blocks
+    #[allow(clippy::struct_field_names)]
+    #[arg(long, env)]
+    pub config_file: Option<PathBuf>,
+
+    /// HLDS Server Addresses, optionally with per server overrides like
+    /// `host:port?interval=10&reply_timeout=2&up_threshold=30`
+    #[arg(long, env, value_parser = server_value_parser, num_args = 1.., default_value = "127.0.0.1:27015", default_value_ifs([("config_file", ArgPredicate::IsPresent, None), ("file_sd_path", ArgPredicate::IsPresent, None), ("master_addr", ArgPredicate::IsPresent, None)]))]
+    pub server_addr: Vec<ServerSpec>,
+
+    /// Interval between queries in seconds
+    #[arg(long, env, value_parser = seconds_value_parser, default_value = "5")]
+    pub interval: Duration,
+
+    /// Time to wait for a reply in seconds
+    #[arg(long, env, value_parser = seconds_value_parser, default_value = "5")]
+    pub reply_timeout: Duration,
+
+    /// Time without replies after which server is considered down in seconds
+    #[arg(long, env, value_parser = seconds_value_parser, default_value = "5")]
+    pub up_threshold: Duration,
+
+    /// Interval between resolving server hostnames again in seconds
+    #[arg(long, env, value_parser = seconds_value_parser, default_value = "300")]
+    pub dns_ttl: Duration,
+
+    /// Master server to discover game servers from, e.g.
+    /// `hl1master.steampowered.com:27011`
+    #[arg(long, env)]
+    pub master_addr: Option<String>,
+
+    /// Filter of the master server query like `\gamedir\cstrike`
+    #[arg(long, env, default_value = "")]
+    pub master_filter: String,
+
+    /// Region of the master server query, 255 for the whole world
+    #[arg(long, env, default_value_t = 255)]
+    pub master_region: u8,
+
+    /// Maximum number of servers discovered from the master server
+    #[arg(long, env, default_value_t = 1000)]
+    pub master_limit: usize,
+
+    /// Interval between master server queries in seconds
+    #[arg(long, env, value_parser = seconds_value_parser, default_value = "300")]
+    pub master_interval: Duration,
+
+    /// Prometheus `file_sd` JSON or YAML file with servers to monitor
+    #[arg(long, env)]
+    pub file_sd_path: Option<PathBuf>,
+
+    /// Interval between checks of the `file_sd` file for changes in seconds
+    #[arg(long, env, value_parser = seconds_value_parser, default_value = "10")]
+    pub file_sd_interval: Duration,
+
+    /// Address for receiving server logs sent with `logaddress_add`
+    #[arg(long, env)]
+    pub log_listen_addr: Option<SocketAddr>,
 
     /// UDP Bind Address
     #[arg(long, env, default_value = "0.0.0.0:0")]
     pub listen_addr: SocketAddr,
+
+    /// Buckets of the query duration histogram in seconds
+    #[arg(
+        long,
+        env,
+        value_delimiter = ',',
+        default_value = "0.005,0.01,0.025,0.05,0.1,0.25,0.5,1,2.5"
+    )]
+    pub query_duration_buckets: Vec<f64>,
+
+    /// Query player lists and export per-player metrics
+    #[arg(long, env, default_value_t = false)]
+    pub query_players: bool,
+
+    /// Server rules (cvars) exported as numeric gauges
+    #[arg(long, env, value_delimiter = ',')]
+    pub rule_gauges: Vec<String>,
+
+    /// Server rules (cvars) exported as labels of an info metric
+    #[arg(long, env, value_delimiter = ',')]
+    pub rule_labels: Vec<String>,
+
+    /// Weapons reported in per-weapon kill metrics, others are reported as
+    /// `other`. All weapons are reported if empty
+    #[arg(long, env, value_delimiter = ',')]
+    pub weapons: Vec<String>,
+
+    /// Windows of unique player counts in seconds
+    #[arg(long, env, value_parser = seconds_value_parser, value_delimiter = ',', default_value = "3600,86400")]
+    pub session_windows: Vec<Duration>,
+
+    /// Maximum number of players tracked per server for sessions and unique
+    /// player counts
+    #[arg(long, env, default_value_t = 10000)]
+    pub session_player_limit: usize,
+
+    /// Player counts at which `players_above` and `players_below` events
+    /// are emitted
+    #[arg(long, env, value_delimiter = ',')]
+    pub player_thresholds: Vec<u8>,
+
+    /// `SQLite` file where history of servers and players is stored
+    #[arg(long, env)]
+    pub history_path: Option<PathBuf>,
+
+    /// Time history is kept for in seconds
+    #[arg(long, env, value_parser = seconds_value_parser, default_value = "2592000")]
+    pub history_retention: Duration,
+
+    /// Age of server samples after which they are downsampled in seconds
+    #[arg(long, env, value_parser = seconds_value_parser, default_value = "86400")]
+    pub history_downsample_after: Duration,
+
+    /// Resolution of downsampled server samples in seconds
+    #[arg(long, env, value_parser = seconds_value_parser, default_value = "300")]
+    pub history_downsample_interval: Duration,
+
+    /// RCON password used to scrape `stats` and `status` of servers
+    #[arg(long, env, hide_env_values = true, value_parser = secret_value_parser)]
+    #[serde(skip)]
+    pub rcon_password: Option<Secret>,
+}
+
+/// Structure of the configuration file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default)]
+    pub servers: Vec<ServerSpec>,
+    #[serde(default)]
+    pub modules: BTreeMap<String, ModuleSpec>,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookSpec>,
+}
+
+/// Query options of a `/probe` module
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModuleSpec {
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub reply_timeout: Option<Duration>,
+    pub query_players: Option<bool>,
+    pub rule_gauges: Option<Vec<String>>,
+    pub rule_labels: Option<Vec<String>>,
+}
+
+/// Webhook notified of server events with a JSON payload
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookSpec {
+    /// Kept secret as URLs of chat webhooks contain tokens
+    pub url: Secret,
+    /// Events sent to the webhook, all but player joins and leaves if empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Payloads by event type with `{field}` placeholders of the event
+    #[serde(default)]
+    pub templates: BTreeMap<String, String>,
+    /// Time changes of a server are combined for before they are sent
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub debounce: Option<Duration>,
+    pub retries: Option<u32>,
+}
+
+/// Game server address with optional overrides of global options
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerSpec {
+    /// Address as configured, hostnames are resolved again periodically
+    pub addr: String,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub interval: Option<Duration>,
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub reply_timeout: Option<Duration>,
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub up_threshold: Option<Duration>,
+    pub query_players: Option<bool>,
+    pub rule_gauges: Option<Vec<String>>,
+    pub rule_labels: Option<Vec<String>>,
+    pub weapons: Option<Vec<String>>,
+    #[serde(skip_serializing)]
+    pub rcon_password: Option<Secret>,
+}
+
+impl From<SocketAddr> for ServerSpec {
+    fn from(addr: SocketAddr) -> Self {
+        Self {
+            addr: addr.to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Query options of a single game server
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ServerOptions {
+    /// Hostname with port if server is not configured with an IP address
+    pub host: Option<String>,
+    pub name: Option<String>,
+    pub labels: BTreeMap<String, String>,
+    pub interval: Duration,
+    pub reply_timeout: Duration,
+    pub up_threshold: Duration,
+    pub query_players: bool,
+    pub rule_gauges: Vec<String>,
+    pub rule_labels: Vec<String>,
+    pub weapons: Vec<String>,
+    pub session_windows: Vec<Duration>,
+    pub session_player_limit: usize,
+    pub player_thresholds: Vec<u8>,
+    #[serde(skip)]
+    pub rcon_password: Option<Secret>,
+}
+
+/// Password which is never printed or logged
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+impl ServerOptions {
+    pub const fn query_rules(&self) -> bool {
+        !self.rule_gauges.is_empty() || !self.rule_labels.is_empty()
+    }
+
+    /// Label of a weapon in per-weapon metrics
+    pub fn weapon_label<'a>(&self, weapon: &'a str) -> &'a str {
+        if self.weapons.is_empty() || self.weapons.iter().any(|w| w == weapon)
+        {
+            weapon
+        } else {
+            "other"
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.reply_timeout > self.interval {
+            bail!(
+                "Reply timeout {:?} is longer than interval {:?}",
+                self.reply_timeout,
+                self.interval
+            );
+        }
+        if self.up_threshold < self.interval {
+            bail!(
+                "Up threshold {:?} is shorter than interval {:?}",
+                self.up_threshold,
+                self.interval
+            );
+        }
+        for name in self.labels.keys() {
+            if !is_label_name(name) {
+                bail!("Invalid label name: {name}");
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_label_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
 }
 
 #[derive(ValueEnum, Debug, Clone, Copy, Serialize)]
@@ -69,7 +346,258 @@ fn socketaddr_value_parser(value: &str) -> anyhow::Result<SocketAddr> {
     }
 }
 
+/// Checks the `host:port` syntax, leaving name resolution to the workers
+fn check_host_port(value: &str) -> anyhow::Result<()> {
+    if value.parse::<SocketAddr>().is_ok() {
+        return Ok(());
+    }
+    let (host, port) = value
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("Missing port: {value}"))?;
+    if host.is_empty() || host.contains(':') {
+        bail!("Invalid host: {value}");
+    }
+    port.parse::<u16>()
+        .map_err(|e| anyhow!("Invalid port: {value}: {e}"))?;
+    Ok(())
+}
+
+fn seconds_value_parser(value: &str) -> anyhow::Result<Duration> {
+    let duration = Duration::try_from_secs_f64(value.parse()?)?;
+    if duration.is_zero() {
+        bail!("Duration must be positive");
+    }
+    Ok(duration)
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn secret_value_parser(value: &str) -> anyhow::Result<Secret> {
+    Ok(Secret(value.to_owned()))
+}
+
+fn server_value_parser(value: &str) -> anyhow::Result<ServerSpec> {
+    let (addr, overrides) = value.split_once('?').unwrap_or((value, ""));
+    let mut spec = ServerSpec {
+        addr: addr.to_string(),
+        name: None,
+        labels: BTreeMap::new(),
+        interval: None,
+        reply_timeout: None,
+        up_threshold: None,
+        query_players: None,
+        rule_gauges: None,
+        rule_labels: None,
+        weapons: None,
+        rcon_password: None,
+    };
+    check_host_port(addr)?;
+    for pair in overrides.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid server option: {pair}"))?;
+        let value = Some(seconds_value_parser(value)?);
+        match key {
+            "interval" => spec.interval = value,
+            "reply_timeout" => spec.reply_timeout = value,
+            "up_threshold" => spec.up_threshold = value,
+            _ => bail!("Unknown server option: {key}"),
+        }
+    }
+    Ok(spec)
+}
+
+fn deserialize_seconds<'de, D>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let Some(value) = Option::<f64>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    seconds_value_parser(&value.to_string())
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
 impl Config {
+    /// Rejects global options which contradict each other
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ServerOptions {
+            interval: self.interval,
+            reply_timeout: self.reply_timeout,
+            up_threshold: self.up_threshold,
+            ..ServerOptions::default()
+        }
+        .validate()
+    }
+
+    /// Servers from the configuration file followed by command line ones
+    pub fn servers(&self) -> anyhow::Result<Vec<ServerSpec>> {
+        let mut servers = match &self.config_file {
+            Some(path) => Self::read_config_file(path)?.servers,
+            None => vec![],
+        };
+        servers.extend(self.server_addr.iter().cloned());
+        Ok(servers)
+    }
+
+    /// Options of all configured servers by their configured address,
+    /// skipping duplicate addresses. Hostnames are resolved by `Servers`
+    pub fn server_list(&self) -> anyhow::Result<Vec<(String, ServerOptions)>> {
+        let mut servers: Vec<(String, ServerOptions)> = vec![];
+        for spec in self.servers()? {
+            if servers.iter().any(|(addr, _)| *addr == spec.addr) {
+                tracing::warn!(
+                    "Duplicate server address: {}. Skipping",
+                    spec.addr
+                );
+                continue;
+            }
+            servers.push((spec.addr.clone(), self.server_options(&spec)?));
+        }
+        Ok(servers)
+    }
+
+    /// Configured servers followed by discovered ones which are not
+    /// configured explicitly. Invalid discovered servers are skipped and
+    /// the global RCON password is never sent to them
+    pub fn server_list_with(
+        &self,
+        discovered: &[ServerSpec],
+    ) -> anyhow::Result<Vec<(String, ServerOptions)>> {
+        let mut servers = self.server_list()?;
+        let mut known: HashSet<_> =
+            servers.iter().map(|(addr, _)| addr.clone()).collect();
+        for spec in discovered {
+            if !known.insert(spec.addr.clone()) {
+                continue;
+            }
+            match self.server_options(spec) {
+                Ok(options) => servers.push((
+                    spec.addr.clone(),
+                    ServerOptions {
+                        rcon_password: spec.rcon_password.clone(),
+                        ..options
+                    },
+                )),
+                Err(e) => tracing::warn!("{:#}. Skipping", e),
+            }
+        }
+        Ok(servers)
+    }
+
+    /// Options of probe modules from the configuration file
+    pub fn probe_modules(
+        &self,
+    ) -> anyhow::Result<HashMap<String, ServerOptions>> {
+        let modules = match &self.config_file {
+            Some(path) => Self::read_config_file(path)?.modules,
+            None => BTreeMap::new(),
+        };
+        Ok(modules
+            .into_iter()
+            .map(|(name, spec)| (name, self.probe_options(&spec)))
+            .collect())
+    }
+
+    /// Webhooks from the configuration file
+    pub fn webhooks(&self) -> anyhow::Result<Vec<WebhookSpec>> {
+        let Some(path) = &self.config_file else {
+            return Ok(vec![]);
+        };
+        let webhooks = Self::read_config_file(path)?.webhooks;
+        for webhook in &webhooks {
+            let names = webhook.events.iter().chain(webhook.templates.keys());
+            for name in names {
+                if !ServerEvent::LABELS.contains(&name.as_str()) {
+                    bail!("Unknown webhook event: {name}");
+                }
+            }
+        }
+        Ok(webhooks)
+    }
+
+    /// Options of a single probe, global values are used for the ones
+    /// which module doesn't set
+    pub fn probe_options(&self, spec: &ModuleSpec) -> ServerOptions {
+        let reply_timeout = spec.reply_timeout.unwrap_or(self.reply_timeout);
+        ServerOptions {
+            interval: reply_timeout,
+            reply_timeout,
+            up_threshold: reply_timeout,
+            query_players: spec.query_players.unwrap_or(self.query_players),
+            rule_gauges: spec
+                .rule_gauges
+                .clone()
+                .unwrap_or_else(|| self.rule_gauges.clone()),
+            rule_labels: spec
+                .rule_labels
+                .clone()
+                .unwrap_or_else(|| self.rule_labels.clone()),
+            ..ServerOptions::default()
+        }
+    }
+
+    fn read_config_file(path: &PathBuf) -> anyhow::Result<ConfigFile> {
+        let content = std::fs::read_to_string(path).with_context(|| {
+            format!("Can't read config file {}", path.display())
+        })?;
+        toml::from_str(&content).with_context(|| {
+            format!("Can't parse config file {}", path.display())
+        })
+    }
+
+    pub fn server_options(
+        &self,
+        spec: &ServerSpec,
+    ) -> anyhow::Result<ServerOptions> {
+        let interval = spec.interval.unwrap_or(self.interval);
+        // Global values are validated against the global interval and are
+        // only adjusted to an interval set for the server
+        let host = spec
+            .addr
+            .parse::<SocketAddr>()
+            .is_err()
+            .then(|| spec.addr.clone());
+        let options = ServerOptions {
+            host,
+            name: spec.name.clone(),
+            labels: spec.labels.clone(),
+            interval,
+            reply_timeout: spec
+                .reply_timeout
+                .unwrap_or_else(|| self.reply_timeout.min(interval)),
+            up_threshold: spec
+                .up_threshold
+                .unwrap_or_else(|| self.up_threshold.max(interval)),
+            query_players: spec.query_players.unwrap_or(self.query_players),
+            rule_gauges: spec
+                .rule_gauges
+                .clone()
+                .unwrap_or_else(|| self.rule_gauges.clone()),
+            rule_labels: spec
+                .rule_labels
+                .clone()
+                .unwrap_or_else(|| self.rule_labels.clone()),
+            weapons: spec
+                .weapons
+                .clone()
+                .unwrap_or_else(|| self.weapons.clone()),
+            session_windows: self.session_windows.clone(),
+            session_player_limit: self.session_player_limit,
+            player_thresholds: self.player_thresholds.clone(),
+            rcon_password: spec
+                .rcon_password
+                .clone()
+                .or_else(|| self.rcon_password.clone()),
+        };
+        options
+            .validate()
+            .map_err(|e| anyhow!("Server {}: {e}", spec.addr))?;
+        Ok(options)
+    }
+
     pub fn log(&self) {
         if let Ok(json_obj) = to_value(self) {
             if let Ok(json_obj) =
@@ -85,9 +613,11 @@ impl Config {
 
 #[cfg(test)]
 mod tests {
-    use std::net::ToSocketAddrs;
+    use std::net::{SocketAddr, ToSocketAddrs};
+
+    use std::time::Duration;
 
-    use super::socketaddr_value_parser;
+    use super::{server_value_parser, socketaddr_value_parser};
 
     #[test]
     fn parse_socketaddr_with_default() {
@@ -102,6 +632,188 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_server_with_overrides() {
+        let spec =
+            server_value_parser("127.0.0.1:27015?interval=10&up_threshold=30")
+                .expect("value_parser failed");
+        assert_eq!(spec.addr, "127.0.0.1:27015");
+        assert_eq!(spec.interval, Some(Duration::from_secs(10)));
+        assert_eq!(spec.reply_timeout, None);
+        assert_eq!(spec.up_threshold, Some(Duration::from_secs(30)));
+
+        assert!(server_value_parser("127.0.0.1:27015?interval=0").is_err());
+        assert!(server_value_parser("127.0.0.1:27015?foo=1").is_err());
+    }
+
+    #[test]
+    fn parse_server_without_resolving() {
+        for value in [
+            "hlds.invalid:27015",
+            "[::1]:27015",
+            "127.0.0.1:27015",
+            "a:1",
+        ] {
+            let spec =
+                server_value_parser(value).expect("value_parser failed");
+            assert_eq!(spec.addr, value);
+        }
+        for value in ["hlds.invalid", ":27015", "::1:27015", "host:port"] {
+            assert!(server_value_parser(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn validate_server_options() {
+        use super::Config;
+        use clap::Parser;
+
+        let config = Config::parse_from(["hlds_exporter"]);
+        assert!(config.validate().is_ok());
+        for args in [
+            ["--interval", "10", "--up-threshold", "5"],
+            ["--interval", "2", "--reply-timeout", "3"],
+        ] {
+            let config = Config::parse_from(
+                std::iter::once("hlds_exporter").chain(args),
+            );
+            assert!(config.validate().is_err());
+        }
+        let spec =
+            server_value_parser("127.0.0.1:27015?interval=10&up_threshold=5")
+                .unwrap();
+        assert!(config.server_options(&spec).is_err());
+
+        let spec = server_value_parser("127.0.0.1:27015?interval=10").unwrap();
+        let options = config.server_options(&spec).expect("valid options");
+        assert_eq!(options.reply_timeout, Duration::from_secs(5));
+        assert_eq!(options.up_threshold, Duration::from_secs(10));
+
+        let spec = server_value_parser("127.0.0.1:27015?interval=1").unwrap();
+        let options = config.server_options(&spec).expect("valid options");
+        assert_eq!(options.reply_timeout, Duration::from_secs(1));
+        assert_eq!(options.host, None);
+
+        let spec = server_value_parser("localhost:27015").unwrap();
+        let options = config.server_options(&spec).expect("valid options");
+        assert_eq!(options.host.as_deref(), Some("localhost:27015"));
+        assert_eq!(options.weapon_label("knife"), "knife");
+
+        let config =
+            Config::parse_from(["hlds_exporter", "--weapons", "ak47,m4a1"]);
+        let options = config.server_options(&spec).expect("valid options");
+        assert_eq!(options.weapon_label("m4a1"), "m4a1");
+        assert_eq!(options.weapon_label("knife"), "other");
+    }
+
+    #[test]
+    fn parse_config_file() {
+        use super::ConfigFile;
+
+        let config: ConfigFile = toml::from_str(
+            r#"
+            [[servers]]
+            name = "Public"
+            addr = "127.0.0.1:27015"
+            labels = { region = "eu" }
+            interval = 2.5
+            query_players = true
+            rule_gauges = ["mp_timelimit"]
+            rcon_password = "hunter2"
+            weapons = ["ak47"]
+
+            [[servers]]
+            addr = "127.0.0.1:27016"
+
+            [modules.full]
+            reply_timeout = 2
+            query_players = true
+
+            [[webhooks]]
+            url = "https://chat.example.com/hooks/token"
+            events = ["down", "up"]
+            debounce = 45
+            templates = { down = '{"text": "{server} is down"}' }
+            "#,
+        )
+        .expect("config should parse");
+
+        let [public, other] = config.servers.as_slice() else {
+            panic!("two servers are expected");
+        };
+        assert_eq!(public.name.as_deref(), Some("Public"));
+        assert_eq!(
+            public.labels.get("region").map(String::as_str),
+            Some("eu")
+        );
+        assert_eq!(public.interval, Some(Duration::from_millis(2500)));
+        assert_eq!(public.query_players, Some(true));
+        let password = public.rcon_password.as_ref().expect("password");
+        assert_eq!(password.expose(), "hunter2");
+        assert!(!format!("{public:?}").contains("hunter2"));
+        assert_eq!(public.weapons, Some(vec!["ak47".to_owned()]));
+        assert!(!serde_json::to_string(public).unwrap().contains("hunter2"));
+        assert_eq!(other.addr, "127.0.0.1:27016");
+        assert!(other.labels.is_empty());
+        assert_eq!(other.interval, None);
+        let full = config.modules.get("full").expect("module should parse");
+        assert_eq!(full.reply_timeout, Some(Duration::from_secs(2)));
+        assert_eq!(full.query_players, Some(true));
+        let [webhook] = config.webhooks.as_slice() else {
+            panic!("one webhook is expected");
+        };
+        assert_eq!(webhook.events, ["down", "up"]);
+        assert_eq!(webhook.debounce, Some(Duration::from_secs(45)));
+        assert!(webhook.templates.contains_key("down"));
+        assert!(!format!("{webhook:?}").contains("token"));
+
+        assert!(toml::from_str::<ConfigFile>(
+            "[[servers]]\naddr = \"127.0.0.1:27015\"\nfoo = 1"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn keep_rcon_password_from_discovered_servers() {
+        use super::{Config, ServerSpec};
+        use clap::Parser;
+
+        let config = Config::parse_from([
+            "hlds_exporter",
+            "--server-addr",
+            "127.0.0.1:27015",
+            "--rcon-password",
+            "hunter2",
+        ]);
+        let discovered: ServerSpec =
+            "127.0.0.2:27015".parse::<SocketAddr>().unwrap().into();
+        let servers = config.server_list_with(&[discovered]).unwrap();
+        let [(configured, configured_options), (addr, options)] =
+            servers.as_slice()
+        else {
+            panic!("two servers are expected");
+        };
+        assert_eq!(configured, "127.0.0.1:27015");
+        assert!(configured_options.rcon_password.is_some());
+        assert_eq!(addr, "127.0.0.2:27015");
+        assert_eq!(options.rcon_password, None);
+    }
+
+    #[test]
+    fn default_server_without_discovery() {
+        use super::Config;
+        use clap::Parser;
+
+        let config = Config::parse_from(["hlds_exporter"]);
+        assert_eq!(config.server_addr.len(), 1);
+        let config = Config::parse_from([
+            "hlds_exporter",
+            "--master-addr",
+            "hl1master.steampowered.com:27011",
+        ]);
+        assert!(config.server_addr.is_empty());
+    }
+
     #[test]
     fn verify_cli() {
         use super::Config;
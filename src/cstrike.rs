@@ -0,0 +1,155 @@
+use crate::logs::LogEvent;
+
+/// Team triggers which end a round
+static ROUND_WIN_TRIGGERS: &[&str] = &[
+    "All_Hostages_Rescued",
+    "Bomb_Defused",
+    "CTs_PreventEscape",
+    "CTs_Win",
+    "Escaping_Terrorists_Neutralized",
+    "Hostages_Not_Rescued",
+    "Round_Draw",
+    "Target_Bombed",
+    "Target_Saved",
+    "Terrorists_Escaped",
+    "Terrorists_Not_Escaped",
+    "Terrorists_Win",
+    "VIP_Assassinated",
+    "VIP_Escaped",
+    "VIP_Not_Escaped",
+];
+
+/// Counter-Strike specific meaning of a log event
+#[derive(Debug, PartialEq, Eq)]
+pub enum Event<'a> {
+    RoundWin { team: &'a str, reason: String },
+    TeamScore { team: &'a str, score: i64 },
+    Bomb(&'static str),
+    Hostage(&'static str),
+    Purchase(&'a str),
+}
+
+/// Interprets a log event of a Counter-Strike server, events of other
+/// mods are ignored
+pub fn events(event: &LogEvent) -> Vec<Event<'_>> {
+    match event {
+        LogEvent::TeamTrigger {
+            team,
+            trigger,
+            properties,
+        } => {
+            let mut events = vec![];
+            if trigger == "Target_Bombed" {
+                events.push(Event::Bomb("exploded"));
+            }
+            if ROUND_WIN_TRIGGERS.contains(&trigger.as_str()) {
+                events.push(Event::RoundWin {
+                    team,
+                    reason: trigger.to_lowercase(),
+                });
+            }
+            // Win triggers carry scores of both teams like `(CT "3") (T "1")`
+            for (key, value) in properties {
+                let team = match key.as_str() {
+                    "CT" => "CT",
+                    "T" => "TERRORIST",
+                    _ => continue,
+                };
+                if let Ok(score) = value.parse() {
+                    events.push(Event::TeamScore { team, score });
+                }
+            }
+            events
+        },
+        LogEvent::TeamScore { team, score } => vec![Event::TeamScore {
+            team,
+            score: *score,
+        }],
+        LogEvent::PlayerTrigger { trigger, .. } => {
+            player_event(trigger).into_iter().collect()
+        },
+        LogEvent::Purchase { item, .. } => vec![Event::Purchase(item)],
+        _ => vec![],
+    }
+}
+
+fn player_event(trigger: &str) -> Option<Event<'static>> {
+    Some(match trigger {
+        "Planted_The_Bomb" => Event::Bomb("planted"),
+        "Defused_The_Bomb" => Event::Bomb("defused"),
+        "Begin_Bomb_Defuse_With_Kit" | "Begin_Bomb_Defuse_Without_Kit" => {
+            Event::Bomb("defuse_started")
+        },
+        "Got_The_Bomb" => Event::Bomb("picked_up"),
+        "Dropped_The_Bomb" => Event::Bomb("dropped"),
+        "Spawned_With_The_Bomb" => Event::Bomb("spawned_with"),
+        "Touched_A_Hostage" => Event::Hostage("touched"),
+        "Rescued_A_Hostage" => Event::Hostage("rescued"),
+        "Killed_A_Hostage" => Event::Hostage("killed"),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{events, Event};
+    use crate::logs::{parse_line, LogEvent};
+
+    fn event(message: &str) -> LogEvent {
+        parse_line(&format!("L 10/17/2026 - 12:00:00: {message}"))
+            .expect("line should parse")
+    }
+
+    #[test]
+    fn interpret_round_wins() {
+        assert_eq!(
+            events(&event(
+                r#"Team "TERRORIST" triggered "Target_Bombed" (CT "2") (T "5")"#
+            )),
+            [
+                Event::Bomb("exploded"),
+                Event::RoundWin {
+                    team: "TERRORIST",
+                    reason: "target_bombed".to_owned(),
+                },
+                Event::TeamScore {
+                    team: "CT",
+                    score: 2
+                },
+                Event::TeamScore {
+                    team: "TERRORIST",
+                    score: 5
+                },
+            ]
+        );
+        assert_eq!(
+            events(&event(r#"Team "CT" scored "3" with "5" players"#)),
+            [Event::TeamScore {
+                team: "CT",
+                score: 3
+            }]
+        );
+        assert!(events(&event(r#"Team "Red" triggered "Captured_Flag""#))
+            .is_empty());
+    }
+
+    #[test]
+    fn interpret_player_triggers() {
+        assert_eq!(
+            events(&event(
+                r#""bob<3><STEAM_0:1:3><TERRORIST>" triggered "Planted_The_Bomb""#
+            )),
+            [Event::Bomb("planted")]
+        );
+        assert_eq!(
+            events(&event(
+                r#""alice<2><STEAM_0:1:2><CT>" triggered "Rescued_A_Hostage""#
+            )),
+            [Event::Hostage("rescued")]
+        );
+        assert_eq!(
+            events(&event(r#""alice<2><STEAM_0:1:2><CT>" purchased "m4a1""#)),
+            [Event::Purchase("m4a1")]
+        );
+    }
+}
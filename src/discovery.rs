@@ -0,0 +1,216 @@
+use std::collections::HashSet;
+use std::io::{Cursor, Read};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use byteorder::{BigEndian, ReadBytesExt};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::Sender;
+use tokio::time;
+
+use crate::config::Config;
+use crate::hlds::MAX_REPLY_SIZE;
+
+const A2M_GET_SERVERS_BATCH2: u8 = 0x31;
+static M2A_SERVER_BATCH: &[u8] = b"\xFF\xFF\xFF\xFF\x66\x0A";
+const FIRST_SEED: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
+
+/// Discovers servers to monitor with the master server query protocol
+pub struct Discovery {
+    master_addr: String,
+    region: u8,
+    filter: String,
+    limit: usize,
+    timeout: Duration,
+}
+
+impl Discovery {
+    pub fn new(config: &Config) -> Option<Self> {
+        Some(Self {
+            master_addr: config.master_addr.clone()?,
+            region: config.master_region,
+            filter: config.master_filter.clone(),
+            limit: config.master_limit,
+            timeout: config.reply_timeout,
+        })
+    }
+
+    /// Queries the master server every interval and sends discovered
+    /// addresses until the receiver is dropped
+    pub async fn run(
+        self,
+        interval: Duration,
+        servers: Sender<Vec<SocketAddr>>,
+    ) {
+        let mut interval = time::interval(interval);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            match self.discover().await {
+                Ok(discovered) => {
+                    tracing::info!(
+                        "Discovered {} servers from {}",
+                        discovered.len(),
+                        self.master_addr
+                    );
+                    if servers.send(discovered).await.is_err() {
+                        break;
+                    }
+                },
+                Err(e) => tracing::warn!(
+                    "Error querying master server {}: {:#}",
+                    self.master_addr,
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Fetches pages of server addresses until the end of the list or the
+    /// limit is reached
+    pub async fn discover(&self) -> anyhow::Result<Vec<SocketAddr>> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        socket.connect(&self.master_addr).await?;
+
+        let mut seen = HashSet::new();
+        let mut discovered = vec![];
+        let mut seed = FIRST_SEED;
+        let mut buf = [0; MAX_REPLY_SIZE];
+        loop {
+            socket
+                .send(&request(self.region, seed, &self.filter))
+                .await?;
+            let amt = time::timeout(self.timeout, socket.recv(&mut buf))
+                .await
+                .context("Master server didn't reply in time")??;
+            let page = parse_reply(buf.get(..amt).unwrap_or_default())?;
+            let Some(last) = page.last().copied() else {
+                break;
+            };
+            for addr in page {
+                if addr != FIRST_SEED && seen.insert(addr) {
+                    discovered.push(SocketAddr::V4(addr));
+                }
+            }
+            if last == FIRST_SEED || discovered.len() >= self.limit {
+                break;
+            }
+            seed = last;
+        }
+        discovered.truncate(self.limit);
+        Ok(discovered)
+    }
+}
+
+/// Builds a request for a page of servers following the seed address
+fn request(region: u8, seed: SocketAddrV4, filter: &str) -> Vec<u8> {
+    let mut msg = vec![A2M_GET_SERVERS_BATCH2, region];
+    msg.extend(seed.to_string().as_bytes());
+    msg.push(0);
+    msg.extend(filter.as_bytes());
+    msg.push(0);
+    msg
+}
+
+/// Parses a page of server addresses, the list ends with `0.0.0.0:0`
+fn parse_reply(packet: &[u8]) -> anyhow::Result<Vec<SocketAddrV4>> {
+    let Some(body) = packet.strip_prefix(M2A_SERVER_BATCH) else {
+        bail!("Invalid master server reply header");
+    };
+    if body.len() % 6 != 0 {
+        bail!("Truncated master server reply");
+    }
+    let mut cursor = Cursor::new(body);
+    let mut addrs = Vec::with_capacity(body.len() / 6);
+    let mut ip = [0; 4];
+    while cursor.read_exact(&mut ip).is_ok() {
+        let port = cursor.read_u16::<BigEndian>()?;
+        addrs.push(SocketAddrV4::new(Ipv4Addr::from(ip), port));
+    }
+    Ok(addrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+    use std::time::Duration;
+
+    use tokio::net::UdpSocket;
+
+    use super::{parse_reply, request, Discovery, M2A_SERVER_BATCH};
+
+    fn reply(addrs: &[SocketAddrV4]) -> Vec<u8> {
+        let mut packet = Vec::from(M2A_SERVER_BATCH);
+        for addr in addrs {
+            packet.extend(addr.ip().octets());
+            packet.extend(addr.port().to_be_bytes());
+        }
+        packet
+    }
+
+    #[test]
+    fn build_request() {
+        let seed = SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 27015);
+        assert_eq!(
+            request(0xFF, seed, "\\gamedir\\cstrike"),
+            b"1\xFF1.2.3.4:27015\0\\gamedir\\cstrike\0"
+        );
+    }
+
+    #[test]
+    fn parse_server_batch() {
+        let server = SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 27015);
+        let end = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
+        let addrs = parse_reply(&reply(&[server, end])).expect("should parse");
+        assert_eq!(addrs, [server, end]);
+
+        let mut truncated = reply(&[server]);
+        truncated.pop();
+        assert!(parse_reply(&truncated).is_err());
+        assert!(parse_reply(b"\xFF\xFF\xFF\xFF\x49").is_err());
+    }
+
+    #[tokio::test]
+    async fn discover_from_fake_master() {
+        let master = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let master_addr = master.local_addr().unwrap();
+        let first = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 27015);
+        let second = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 27016);
+        let end = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
+        tokio::spawn(async move {
+            let mut buf = [0; 1400];
+            loop {
+                let (amt, src) = master.recv_from(&mut buf).await.unwrap();
+                let msg = buf.get(..amt).unwrap();
+                assert!(msg.ends_with(b"\\gamedir\\cstrike\0"));
+                // Pages follow the last address of the previous one
+                let page = if msg.starts_with(b"1\xFF0.0.0.0:0\0") {
+                    reply(&[first, first])
+                } else if msg.starts_with(b"1\xFF10.0.0.1:27015\0") {
+                    reply(&[second, end])
+                } else {
+                    panic!("unexpected seed");
+                };
+                master.send_to(&page, src).await.unwrap();
+            }
+        });
+
+        let mut discovery = Discovery {
+            master_addr: master_addr.to_string(),
+            region: 0xFF,
+            filter: "\\gamedir\\cstrike".to_owned(),
+            limit: 10,
+            timeout: Duration::from_secs(1),
+        };
+        let discovered = discovery.discover().await.expect("should discover");
+        assert_eq!(
+            discovered,
+            [SocketAddr::V4(first), SocketAddr::V4(second)]
+        );
+
+        discovery.limit = 1;
+        let discovered = discovery.discover().await.expect("should discover");
+        assert_eq!(discovered, [SocketAddr::V4(first)]);
+    }
+}
@@ -0,0 +1,240 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tiny_http::{Request, Response};
+use tokio::runtime::Handle;
+use tokio::sync::broadcast::{self, error::RecvError, Sender};
+use tokio::time;
+
+/// Events kept for subscribers which fall behind
+const EVENT_BUFFER: usize = 256;
+/// Interval of comments which keep idle streams open and detect clients
+/// which went away
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+/// Streams served at once, each one takes a thread until its client is
+/// found to be gone
+const MAX_SUBSCRIBERS: usize = 32;
+
+static STREAM_HEADER: &str = "HTTP/1.1 200 OK\r\n\
+    Content-Type: text/event-stream\r\n\
+    Cache-Control: no-cache\r\n\
+    Connection: close\r\n\r\n";
+
+/// State change of a game server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    Up {
+        server: String,
+    },
+    Down {
+        server: String,
+    },
+    MapChange {
+        server: String,
+        from: String,
+        to: String,
+    },
+    NameChange {
+        server: String,
+        from: String,
+        to: String,
+    },
+    PlayerJoin {
+        server: String,
+        player: String,
+    },
+    PlayerLeave {
+        server: String,
+        player: String,
+    },
+    /// Player count reached the threshold
+    PlayersAbove {
+        server: String,
+        threshold: u8,
+        players: u8,
+    },
+    /// Player count fell below the threshold
+    PlayersBelow {
+        server: String,
+        threshold: u8,
+        players: u8,
+    },
+}
+
+impl ServerEvent {
+    pub const LABELS: [&'static str; 8] = [
+        "up",
+        "down",
+        "map_change",
+        "name_change",
+        "player_join",
+        "player_leave",
+        "players_above",
+        "players_below",
+    ];
+
+    pub const fn label(&self) -> &'static str {
+        match self {
+            Self::Up { .. } => "up",
+            Self::Down { .. } => "down",
+            Self::MapChange { .. } => "map_change",
+            Self::NameChange { .. } => "name_change",
+            Self::PlayerJoin { .. } => "player_join",
+            Self::PlayerLeave { .. } => "player_leave",
+            Self::PlayersAbove { .. } => "players_above",
+            Self::PlayersBelow { .. } => "players_below",
+        }
+    }
+
+    pub fn server(&self) -> &str {
+        match self {
+            Self::Up { server }
+            | Self::Down { server }
+            | Self::MapChange { server, .. }
+            | Self::NameChange { server, .. }
+            | Self::PlayerJoin { server, .. }
+            | Self::PlayerLeave { server, .. }
+            | Self::PlayersAbove { server, .. }
+            | Self::PlayersBelow { server, .. } => server,
+        }
+    }
+}
+
+/// Streams events of game servers to `/api/events` subscribers as
+/// server-sent events
+pub struct Events {
+    runtime: Handle,
+    sender: Sender<ServerEvent>,
+    subscribers: Arc<AtomicUsize>,
+}
+
+/// Slot of a stream which is released when the stream ends
+struct Subscription(Arc<AtomicUsize>);
+
+impl Subscription {
+    fn acquire(subscribers: &Arc<AtomicUsize>) -> Option<Self> {
+        subscribers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < MAX_SUBSCRIBERS).then_some(count + 1)
+            })
+            .ok()
+            .map(|_| Self(Arc::clone(subscribers)))
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Events {
+    /// Creates a stream which waits for events on the current tokio runtime
+    pub fn new() -> Self {
+        Self {
+            runtime: Handle::current(),
+            sender: broadcast::channel(EVENT_BUFFER).0,
+            subscribers: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn sender(&self) -> Sender<ServerEvent> {
+        self.sender.clone()
+    }
+
+    /// Streams events to the client in a thread of its own until it
+    /// disconnects, clients over the limit are refused
+    pub fn handle(&self, request: Request) {
+        let Some(subscription) = Subscription::acquire(&self.subscribers)
+        else {
+            let response = Response::from_string("Too many subscribers")
+                .with_status_code(503);
+            if let Err(err) = request.respond(response) {
+                tracing::debug!("Can't send response: {}", err);
+            }
+            return;
+        };
+        let runtime = self.runtime.clone();
+        let mut events = self.sender.subscribe();
+        std::thread::spawn(move || {
+            let _subscription = subscription;
+            let mut writer = request.into_writer();
+            let mut message = STREAM_HEADER.to_owned();
+            loop {
+                if writer
+                    .write_all(message.as_bytes())
+                    .and_then(|()| writer.flush())
+                    .is_err()
+                {
+                    break;
+                }
+                // Timer of the timeout is created within the runtime
+                let next = runtime.block_on(async {
+                    time::timeout(KEEPALIVE_INTERVAL, events.recv()).await
+                });
+                message = match next {
+                    Ok(Ok(event)) => match encode(&event) {
+                        Ok(message) => message,
+                        Err(err) => {
+                            tracing::debug!("Can't encode event: {}", err);
+                            String::new()
+                        },
+                    },
+                    Ok(Err(RecvError::Lagged(skipped))) => {
+                        format!(": skipped {skipped} events\n\n")
+                    },
+                    Ok(Err(RecvError::Closed)) => break,
+                    Err(_elapsed) => ": keepalive\n\n".to_owned(),
+                };
+            }
+        });
+    }
+}
+
+/// Formats an event as `event: <type>` followed by its JSON data
+fn encode(event: &ServerEvent) -> serde_json::Result<String> {
+    Ok(format!(
+        "event: {}\ndata: {}\n\n",
+        event.label(),
+        serde_json::to_string(event)?
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    use super::{encode, ServerEvent, Subscription, MAX_SUBSCRIBERS};
+
+    #[test]
+    fn limit_subscribers() {
+        let subscribers = Arc::new(AtomicUsize::new(0));
+        let subscriptions: Vec<_> = (0..MAX_SUBSCRIBERS)
+            .map(|_| Subscription::acquire(&subscribers))
+            .collect();
+        assert!(subscriptions.iter().all(Option::is_some));
+        assert!(Subscription::acquire(&subscribers).is_none());
+        drop(subscriptions);
+        assert!(Subscription::acquire(&subscribers).is_some());
+    }
+
+    #[test]
+    fn encode_server_event() {
+        let event = ServerEvent::MapChange {
+            server: "127.0.0.1:27015".to_owned(),
+            from: "de_dust2".to_owned(),
+            to: "de_inferno".to_owned(),
+        };
+        assert_eq!(
+            encode(&event).unwrap(),
+            "event: map_change\n\
+            data: {\"type\":\"map_change\",\"server\":\"127.0.0.1:27015\",\
+            \"from\":\"de_dust2\",\"to\":\"de_inferno\"}\n\n"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::Deserialize;
+use tokio::sync::mpsc::Sender;
+use tokio::time;
+
+use crate::config::ServerSpec;
+
+/// Group of targets sharing labels in Prometheus `file_sd` format
+#[derive(Debug, Deserialize)]
+struct TargetGroup {
+    targets: Vec<String>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+}
+
+/// Watches a Prometheus `file_sd` JSON or YAML file for servers to monitor
+pub struct FileSd {
+    path: PathBuf,
+    interval: Duration,
+}
+
+impl FileSd {
+    pub const fn new(path: PathBuf, interval: Duration) -> Self {
+        Self { path, interval }
+    }
+
+    /// Reads the file every interval and sends its servers when content
+    /// changes until the receiver is dropped
+    pub async fn run(self, servers: Sender<Vec<ServerSpec>>) {
+        let mut interval = time::interval(self.interval);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+        let mut last_content = None;
+        loop {
+            interval.tick().await;
+            let content = match tokio::fs::read_to_string(&self.path).await {
+                Ok(content) => content,
+                Err(e) => {
+                    tracing::warn!(
+                        "Can't read targets file {}: {}",
+                        self.path.display(),
+                        e
+                    );
+                    continue;
+                },
+            };
+            if last_content.as_ref() == Some(&content) {
+                continue;
+            }
+            match parse_targets(&self.path, &content) {
+                Ok(targets) => {
+                    tracing::info!(
+                        "Loaded {} servers from {}",
+                        targets.len(),
+                        self.path.display()
+                    );
+                    if servers.send(targets).await.is_err() {
+                        break;
+                    }
+                },
+                // Servers of the previous content are kept
+                Err(e) => tracing::warn!("{:#}", e),
+            }
+            last_content = Some(content);
+        }
+    }
+}
+
+/// Parses target groups into servers, JSON is used unless the file has a
+/// YAML extension
+fn parse_targets(
+    path: &Path,
+    content: &str,
+) -> anyhow::Result<Vec<ServerSpec>> {
+    let is_yaml = path
+        .extension()
+        .is_some_and(|ext| ext == "yml" || ext == "yaml");
+    let groups: anyhow::Result<Vec<TargetGroup>> = if is_yaml {
+        serde_yaml::from_str(content).map_err(anyhow::Error::from)
+    } else {
+        serde_json::from_str(content).map_err(anyhow::Error::from)
+    };
+    let groups = groups.with_context(|| {
+        format!("Can't parse targets file {}", path.display())
+    })?;
+
+    Ok(groups
+        .into_iter()
+        .flat_map(|group| {
+            // Labels with the reserved prefix are meant for relabeling only
+            let labels: BTreeMap<_, _> = group
+                .labels
+                .into_iter()
+                .filter(|(name, _)| !name.starts_with("__"))
+                .collect();
+            group.targets.into_iter().map(move |addr| ServerSpec {
+                addr,
+                labels: labels.clone(),
+                ..ServerSpec::default()
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::parse_targets;
+
+    #[test]
+    fn parse_json_targets() {
+        let servers = parse_targets(
+            Path::new("targets.json"),
+            r#"[
+                {
+                    "targets": ["127.0.0.1:27015", "localhost:27016"],
+                    "labels": { "region": "eu", "__meta_source": "ansible" }
+                },
+                { "targets": ["127.0.0.1:27017"] }
+            ]"#,
+        )
+        .expect("targets should parse");
+
+        let addrs: Vec<_> = servers.iter().map(|s| s.addr.as_str()).collect();
+        assert_eq!(
+            addrs,
+            ["127.0.0.1:27015", "localhost:27016", "127.0.0.1:27017"]
+        );
+        let [first, _, last] = servers.as_slice() else {
+            panic!("three servers are expected");
+        };
+        let labels: Vec<_> = first.labels.keys().collect();
+        assert_eq!(labels, ["region"]);
+        assert!(last.labels.is_empty());
+    }
+
+    #[test]
+    fn parse_yaml_targets() {
+        let servers = parse_targets(
+            Path::new("targets.yml"),
+            "- targets: ['127.0.0.1:27015']\n  labels:\n    region: us\n",
+        )
+        .expect("targets should parse");
+
+        let [server] = servers.as_slice() else {
+            panic!("one server is expected");
+        };
+        assert_eq!(
+            server.labels.get("region").map(String::as_str),
+            Some("us")
+        );
+        assert!(
+            parse_targets(Path::new("targets.json"), "- targets: []").is_err()
+        );
+    }
+}
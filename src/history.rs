@@ -0,0 +1,549 @@
+use std::sync::mpsc::{
+    self, Receiver, RecvTimeoutError, SyncSender, TrySendError,
+};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use tiny_http::{Header, Request, Response};
+
+use crate::config::Config;
+
+/// Records waiting to be written, new ones are dropped when it's full
+const RECORD_BUFFER: usize = 1024;
+/// Interval between applying retention and downsampling
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(30);
+/// Maximum number of samples and sessions returned by a query
+const QUERY_LIMIT: i64 = 10000;
+/// Time queried when the request doesn't set `from`
+const DEFAULT_QUERY_RANGE: i64 = 86400;
+
+static SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS samples (
+    server TEXT NOT NULL,
+    time INTEGER NOT NULL,
+    resolution INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    map TEXT NOT NULL,
+    players INTEGER NOT NULL,
+    max_players INTEGER NOT NULL,
+    bots INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS samples_time ON samples (time, server);
+CREATE TABLE IF NOT EXISTS sessions (
+    server TEXT NOT NULL,
+    player TEXT NOT NULL,
+    start INTEGER NOT NULL,
+    end INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sessions_end ON sessions (end, server, player);
+";
+
+/// Raw samples in each interval are replaced with one of their averages,
+/// the map of an arbitrary sample is kept
+static DOWNSAMPLE: &str = "
+INSERT INTO samples
+SELECT server, time / ?1 * ?1, ?1, name, map, CAST(ROUND(AVG(players)) AS INTEGER),
+    MAX(max_players), CAST(ROUND(AVG(bots)) AS INTEGER)
+FROM samples
+WHERE resolution = 0 AND time < ?2
+GROUP BY server, time / ?1
+";
+
+/// Poll result of a game server
+#[derive(Debug)]
+pub enum Record {
+    Info {
+        server: String,
+        time: i64,
+        name: String,
+        map: String,
+        players: u8,
+        max_players: u8,
+        bots: u8,
+    },
+    /// Names of players with seconds they have been connected for,
+    /// sessions continue if a player was seen within the gap
+    Players {
+        server: String,
+        time: i64,
+        players: Vec<(String, i64)>,
+        gap: i64,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HistoryError {
+    #[error("Invalid {0} parameter")]
+    InvalidParameter(&'static str),
+}
+
+/// Parameters of `/history?server=host:port&player=name&from=ts&to=ts`
+#[derive(Debug, Default, PartialEq, Eq)]
+struct HistoryQuery {
+    server: Option<String>,
+    player: Option<String>,
+    from: i64,
+    to: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct Sample {
+    server: String,
+    time: i64,
+    resolution: i64,
+    name: String,
+    map: String,
+    players: i64,
+    max_players: i64,
+    bots: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct Session {
+    server: String,
+    player: String,
+    start: i64,
+    end: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct HistoryReply {
+    samples: Vec<Sample>,
+    sessions: Vec<Session>,
+}
+
+/// Sends records to the history writer without blocking
+#[derive(Clone)]
+pub struct Recorder(SyncSender<Record>);
+
+impl Recorder {
+    pub fn record(&self, record: Record) {
+        match self.0.try_send(record) {
+            Ok(()) => {},
+            Err(TrySendError::Full(_)) => {
+                tracing::debug!("History buffer is full, dropping record");
+            },
+            Err(TrySendError::Disconnected(_)) => {
+                tracing::debug!("History writer has stopped");
+            },
+        }
+    }
+}
+
+/// Server samples and player sessions stored in a `SQLite` file, times
+/// are in seconds
+pub struct History {
+    db: Mutex<Connection>,
+    retention: i64,
+    downsample_after: i64,
+    downsample_interval: i64,
+}
+
+impl History {
+    pub fn open(config: &Config) -> anyhow::Result<Option<Self>> {
+        let Some(path) = &config.history_path else {
+            return Ok(None);
+        };
+        let db = Connection::open(path).with_context(|| {
+            format!("Can't open history {}", path.display())
+        })?;
+        db.pragma_update(None, "journal_mode", "WAL")?;
+        Self::new(
+            db,
+            seconds(config.history_retention),
+            seconds(config.history_downsample_after),
+            seconds(config.history_downsample_interval),
+        )
+        .map(Some)
+    }
+
+    fn new(
+        db: Connection,
+        retention: i64,
+        downsample_after: i64,
+        downsample_interval: i64,
+    ) -> anyhow::Result<Self> {
+        db.execute_batch(SCHEMA)
+            .context("Can't create history tables")?;
+        Ok(Self {
+            db: Mutex::new(db),
+            retention,
+            downsample_after,
+            downsample_interval,
+        })
+    }
+
+    /// Starts a thread which writes records and applies retention
+    pub fn start(self: &Arc<Self>) -> Recorder {
+        let (tx, rx) = mpsc::sync_channel(RECORD_BUFFER);
+        let history = Arc::clone(self);
+        std::thread::spawn(move || history.write_records(&rx));
+        Recorder(tx)
+    }
+
+    fn write_records(&self, records: &Receiver<Record>) {
+        let mut maintained = Instant::now();
+        self.run_maintenance();
+        loop {
+            match records.recv_timeout(MAINTENANCE_INTERVAL) {
+                Ok(record) => {
+                    if let Err(e) = self.write(&record) {
+                        tracing::warn!("Error writing history: {:#}", e);
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if maintained.elapsed() >= MAINTENANCE_INTERVAL {
+                self.run_maintenance();
+                maintained = Instant::now();
+            }
+        }
+    }
+
+    fn run_maintenance(&self) {
+        if let Err(e) = self.maintain(unix_time()) {
+            tracing::warn!("Error maintaining history: {:#}", e);
+        }
+    }
+
+    fn write(&self, record: &Record) -> anyhow::Result<()> {
+        let mut db = self
+            .db
+            .lock()
+            .map_err(|err| anyhow!("Can't access history {err}"))?;
+        match record {
+            Record::Info {
+                server,
+                time,
+                name,
+                map,
+                players,
+                max_players,
+                bots,
+            } => {
+                db.execute(
+                    "INSERT INTO samples VALUES (?1, ?2, 0, ?3, ?4, ?5, ?6, ?7)",
+                    params![server, time, name, map, players, max_players, bots],
+                )?;
+            },
+            Record::Players {
+                server,
+                time,
+                players,
+                gap,
+            } => {
+                let tx = db.transaction()?;
+                for (player, connected) in players {
+                    let updated = tx.execute(
+                        "UPDATE sessions SET end = ?3 WHERE rowid = (
+                            SELECT rowid FROM sessions
+                            WHERE server = ?1 AND player = ?2 AND end >= ?4
+                            ORDER BY end DESC LIMIT 1
+                        )",
+                        params![server, player, time, time - gap],
+                    )?;
+                    if updated == 0 {
+                        tx.execute(
+                            "INSERT INTO sessions VALUES (?1, ?2, ?3, ?4)",
+                            params![server, player, time - connected, time],
+                        )?;
+                    }
+                }
+                tx.commit()?;
+            },
+        }
+        drop(db);
+        Ok(())
+    }
+
+    /// Downsamples old samples and deletes everything past retention
+    fn maintain(&self, now: i64) -> anyhow::Result<()> {
+        let step = self.downsample_interval.max(1);
+        // Aligned to the interval so every downsampled interval is complete
+        let cutoff = now.saturating_sub(self.downsample_after) / step * step;
+        let expired = now.saturating_sub(self.retention);
+        let mut db = self
+            .db
+            .lock()
+            .map_err(|err| anyhow!("Can't access history {err}"))?;
+        let tx = db.transaction()?;
+        tx.execute(DOWNSAMPLE, params![step, cutoff])?;
+        tx.execute(
+            "DELETE FROM samples WHERE resolution = 0 AND time < ?1",
+            [cutoff],
+        )?;
+        tx.execute("DELETE FROM samples WHERE time < ?1", [expired])?;
+        tx.execute("DELETE FROM sessions WHERE end < ?1", [expired])?;
+        tx.commit()?;
+        drop(db);
+        Ok(())
+    }
+
+    /// Answers a query with samples and sessions in JSON
+    pub fn handle(&self, request: Request) {
+        let response = match params(request.url(), unix_time()) {
+            Ok(query) => match self.query(&query) {
+                Ok(reply) => match serde_json::to_string(&reply) {
+                    Ok(body) => Response::from_string(body).with_header(
+                        Header::from_bytes("Content-Type", "application/json")
+                            .expect("header should be valid"),
+                    ),
+                    Err(err) => Response::from_string(err.to_string())
+                        .with_status_code(500),
+                },
+                Err(err) => {
+                    tracing::warn!("Error querying history: {:#}", err);
+                    Response::from_string("Can't query history")
+                        .with_status_code(500)
+                },
+            },
+            Err(err) => {
+                Response::from_string(err.to_string()).with_status_code(400)
+            },
+        };
+        if let Err(err) = request.respond(response) {
+            tracing::debug!("Can't send response: {}", err);
+        }
+    }
+
+    fn query(&self, query: &HistoryQuery) -> anyhow::Result<HistoryReply> {
+        let db = self
+            .db
+            .lock()
+            .map_err(|err| anyhow!("Can't access history {err}"))?;
+        let samples = db
+            .prepare_cached(
+                "SELECT * FROM samples
+                WHERE (?1 IS NULL OR server = ?1) AND time BETWEEN ?2 AND ?3
+                ORDER BY time, server LIMIT ?4",
+            )?
+            .query_map(
+                params![query.server, query.from, query.to, QUERY_LIMIT],
+                |row| {
+                    Ok(Sample {
+                        server: row.get(0)?,
+                        time: row.get(1)?,
+                        resolution: row.get(2)?,
+                        name: row.get(3)?,
+                        map: row.get(4)?,
+                        players: row.get(5)?,
+                        max_players: row.get(6)?,
+                        bots: row.get(7)?,
+                    })
+                },
+            )?
+            .collect::<Result<_, _>>()?;
+        let sessions = db
+            .prepare_cached(
+                "SELECT * FROM sessions
+                WHERE (?1 IS NULL OR server = ?1)
+                    AND (?2 IS NULL OR player = ?2)
+                    AND end >= ?3 AND start <= ?4
+                ORDER BY start, server, player LIMIT ?5",
+            )?
+            .query_map(
+                params![
+                    query.server,
+                    query.player,
+                    query.from,
+                    query.to,
+                    QUERY_LIMIT
+                ],
+                |row| {
+                    Ok(Session {
+                        server: row.get(0)?,
+                        player: row.get(1)?,
+                        start: row.get(2)?,
+                        end: row.get(3)?,
+                    })
+                },
+            )?
+            .collect::<Result<_, _>>()?;
+        drop(db);
+        Ok(HistoryReply { samples, sessions })
+    }
+}
+
+/// Parses query parameters, the last day is queried by default
+fn params(url: &str, now: i64) -> Result<HistoryQuery, HistoryError> {
+    let query = url.split_once('?').map_or("", |(_, query)| query);
+    let mut result = HistoryQuery::default();
+    let mut from = None;
+    let mut to = None;
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        match key.as_ref() {
+            "server" => result.server = Some(value.into_owned()),
+            "player" => result.player = Some(value.into_owned()),
+            "from" => from = Some(timestamp(&value, "from")?),
+            "to" => to = Some(timestamp(&value, "to")?),
+            _ => {},
+        }
+    }
+    result.to = to.unwrap_or(now);
+    result.from = from.unwrap_or(result.to - DEFAULT_QUERY_RANGE);
+    Ok(result)
+}
+
+fn timestamp(value: &str, name: &'static str) -> Result<i64, HistoryError> {
+    value.parse().map_err(|_err: std::num::ParseIntError| {
+        HistoryError::InvalidParameter(name)
+    })
+}
+
+fn seconds(duration: Duration) -> i64 {
+    i64::try_from(duration.as_secs()).unwrap_or(i64::MAX)
+}
+
+pub fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::{params, History, HistoryError, HistoryQuery, Record};
+
+    fn history() -> History {
+        History::new(Connection::open_in_memory().unwrap(), 86400, 3600, 300)
+            .expect("history should open")
+    }
+
+    fn info(time: i64, map: &str, players: u8) -> Record {
+        Record::Info {
+            server: "127.0.0.1:27015".to_owned(),
+            time,
+            name: "Public".to_owned(),
+            map: map.to_owned(),
+            players,
+            max_players: 32,
+            bots: 0,
+        }
+    }
+
+    fn players(time: i64, names: &[&str]) -> Record {
+        Record::Players {
+            server: "127.0.0.1:27015".to_owned(),
+            time,
+            players: names
+                .iter()
+                .map(|name| ((*name).to_owned(), 30))
+                .collect(),
+            gap: 25,
+        }
+    }
+
+    fn query(from: i64, to: i64) -> HistoryQuery {
+        HistoryQuery {
+            from,
+            to,
+            ..HistoryQuery::default()
+        }
+    }
+
+    #[test]
+    fn track_player_sessions() {
+        let history = history();
+        for record in [
+            players(1000, &["alice", "bob"]),
+            players(1005, &["alice"]),
+            players(1010, &["alice"]),
+            // Bob was away for longer than the gap
+            players(1030, &["alice", "bob"]),
+        ] {
+            history.write(&record).expect("record should be written");
+        }
+
+        let reply = history.query(&query(0, 2000)).unwrap();
+        let sessions: Vec<_> = reply
+            .sessions
+            .iter()
+            .map(|s| (s.player.as_str(), s.start, s.end))
+            .collect();
+        assert_eq!(
+            sessions,
+            [
+                ("alice", 970, 1030),
+                ("bob", 970, 1000),
+                ("bob", 1000, 1030)
+            ]
+        );
+
+        let reply = history
+            .query(&HistoryQuery {
+                player: Some("bob".to_owned()),
+                ..query(1020, 2000)
+            })
+            .unwrap();
+        assert_eq!(reply.sessions.len(), 1);
+    }
+
+    #[test]
+    fn downsample_and_expire_samples() {
+        let history = history();
+        let day = 86400;
+        for record in [
+            info(day, "de_dust2", 1),
+            info(day + 10, "de_dust2", 2),
+            info(day + 299, "de_dust2", 4),
+            info(day + 300, "de_inferno", 8),
+            info(2 * day, "de_nuke", 16),
+        ] {
+            history.write(&record).expect("record should be written");
+        }
+        history
+            .maintain(2 * day)
+            .expect("history should be maintained");
+
+        let reply = history.query(&query(0, 3 * day)).unwrap();
+        let samples: Vec<_> = reply
+            .samples
+            .iter()
+            .map(|s| (s.time, s.resolution, s.map.as_str(), s.players))
+            .collect();
+        assert_eq!(
+            samples,
+            [
+                (day, 300, "de_dust2", 2),
+                (day + 300, 300, "de_inferno", 8),
+                (2 * day, 0, "de_nuke", 16),
+            ]
+        );
+
+        history
+            .maintain(3 * day)
+            .expect("history should be maintained");
+        let reply = history.query(&query(0, 3 * day)).unwrap();
+        assert_eq!(reply.samples.len(), 1);
+    }
+
+    #[test]
+    fn parse_history_params() {
+        assert_eq!(
+            params("/history", 100_000).unwrap(),
+            query(13600, 100_000)
+        );
+        assert_eq!(
+            params(
+                "/history?server=127.0.0.1%3A27015&player=bob&from=10&to=20",
+                100_000
+            )
+            .unwrap(),
+            HistoryQuery {
+                server: Some("127.0.0.1:27015".to_owned()),
+                player: Some("bob".to_owned()),
+                ..query(10, 20)
+            }
+        );
+        assert!(matches!(
+            params("/history?from=yesterday", 0),
+            Err(HistoryError::InvalidParameter("from"))
+        ));
+    }
+}
@@ -1,20 +1,40 @@
 use anyhow::anyhow;
 use byteorder::{LittleEndian, ReadBytesExt};
+use serde::Serialize;
+use std::collections::HashMap;
 use std::io::{Cursor, Read};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::net::UdpSocket;
 use tokio::select;
-use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::time::Interval;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::watch;
+use tokio::time::{self, Interval};
 
+use crate::api::InfoStatus;
+use crate::config::ServerOptions;
+use crate::cstrike;
+use crate::events::ServerEvent;
+use crate::history::{self, Record, Recorder};
+use crate::logs::LogEvent;
 use crate::metrics::Metrics;
+use crate::rcon::{self, Reply};
+use crate::sessions::Sessions;
+use crate::split::{SplitError, SplitPackets};
 
 pub const MAX_REPLY_SIZE: usize = 1400;
 
 static A2S_INFO: &[u8] = b"\xFF\xFF\xFF\xFF\x54Source Engine Query\0";
 const S2A_INFO: u8 = 0x49;
+const S2A_INFO_OBSOLETE: u8 = 0x6D;
+
+static A2S_PLAYER: &[u8] = b"\xFF\xFF\xFF\xFF\x55";
+const S2A_PLAYER: u8 = 0x44;
+
+static A2S_RULES: &[u8] = b"\xFF\xFF\xFF\xFF\x56";
+const S2A_RULES: u8 = 0x45;
 
 const S2C_CHALLENGE: u8 = 0x41;
 
@@ -22,11 +42,20 @@ static SPLIT_PACKET: &[u8] = b"\xFE\xFF\xFF\xFF";
 static HEADER: &[u8] = b"\xFF\xFF\xFF\xFF";
 const CHALLENGE_LENGHT: usize = 4;
 
+const EDF_PORT: u8 = 0x80;
+const EDF_STEAM_ID: u8 = 0x10;
+const EDF_SOURCE_TV: u8 = 0x40;
+const EDF_KEYWORDS: u8 = 0x20;
+const EDF_GAME_ID: u8 = 0x01;
+/// Player time is not accumulated over longer gaps between info replies
+const MAX_MAP_SAMPLE_GAP: Duration = Duration::from_secs(15);
+static EMPTY_CHALLENGE: &[u8] = b"\xFF\xFF\xFF\xFF";
+
 #[derive(Debug)]
 #[repr(u8)]
 enum ServerType {
     Dedicated = b'd',
-    Listen = b'i',
+    Listen = b'l',
     Proxy = b'p',
 }
 
@@ -34,7 +63,7 @@ impl TryFrom<u8> for ServerType {
     type Error = anyhow::Error;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
+        match value.to_ascii_lowercase() {
             b'd' => Ok(Self::Dedicated),
             b'l' => Ok(Self::Listen),
             b'p' => Ok(Self::Proxy),
@@ -55,7 +84,7 @@ impl TryFrom<u8> for EnvironmentType {
     type Error = anyhow::Error;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
+        match value.to_ascii_lowercase() {
             b'l' => Ok(Self::Linux),
             b'w' => Ok(Self::Windows),
             b'm' => Ok(Self::Mac),
@@ -102,10 +131,97 @@ impl TryFrom<u8> for Vac {
     }
 }
 
+#[derive(Debug)]
+#[repr(u8)]
+enum ModType {
+    SingleAndMultiplayer = 0,
+    MultiplayerOnly = 1,
+}
+
+impl TryFrom<u8> for ModType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::SingleAndMultiplayer),
+            1 => Ok(Self::MultiplayerOnly),
+            _ => Err(anyhow!("Invalid mod type")),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[repr(u8)]
+enum ModDll {
+    HalfLife = 0,
+    Own = 1,
+}
+
+impl TryFrom<u8> for ModDll {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::HalfLife),
+            1 => Ok(Self::Own),
+            _ => Err(anyhow!("Invalid mod DLL")),
+        }
+    }
+}
+
+/// Mod details of the obsolete `GoldSrc` info response
+#[derive(Debug)]
+struct ModInfo {
+    link: String,
+    download_link: String,
+    version: i32,
+    size: i32,
+    type_: ModType,
+    dll: ModDll,
+}
+
+/// Kind of query sent to a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Query {
+    Info,
+    Players,
+    Rules,
+}
+
+impl Query {
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Players => "players",
+            Self::Rules => "rules",
+        }
+    }
+}
+
+/// Current map of a server and when it was last observed
+#[derive(Debug)]
+struct CurrentMap {
+    name: String,
+    players: u8,
+    observed: Instant,
+}
+
+/// Optional fields announced by the extra data flag of `S2A_INFO`
+#[derive(Debug, Default)]
+struct ExtraData {
+    port: Option<u16>,
+    steam_id: Option<u64>,
+    source_tv_port: Option<u16>,
+    source_tv_name: Option<String>,
+    keywords: Option<String>,
+    game_id: Option<u64>,
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 struct ServerInfo {
     header: u8,
+    address: Option<String>,
     protocol: u8,
     name: String,
     map: String,
@@ -120,63 +236,366 @@ struct ServerInfo {
     visibility: Visibility,
     vac: Vac,
     version: String,
+    extra: Option<ExtraData>,
+    mod_info: Option<ModInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlayerInfo {
+    pub index: u8,
+    pub name: String,
+    pub score: i32,
+    pub duration: f32,
+}
+
+#[derive(Debug)]
+struct PlayerList {
+    players: Vec<PlayerInfo>,
+}
+
+#[derive(Debug)]
+struct RuleList {
+    rules: Vec<(String, String)>,
 }
 
 pub struct GameServer {
     pub(crate) server_addr: SocketAddr,
+    rx_addr: watch::Receiver<SocketAddr>,
+    options: ServerOptions,
     interval: Interval,
-    rx_challenge: Receiver<Vec<u8>>,
-    tx_challenge: Sender<Vec<u8>>,
     rx_packet: Receiver<Vec<u8>>,
+    rx_log: Receiver<LogEvent>,
     socket: Arc<UdpSocket>,
 
     last_update: Option<Instant>,
     challenge: Vec<u8>,
+    split: SplitPackets,
+    rcon_output: rcon::PrintBuffer,
+    current_map: Option<CurrentMap>,
+    /// Obsolete info reply waiting for the end of the tick
+    obsolete_info: Option<ServerInfo>,
+    /// Whether the server replies with `S2A_INFO`
+    current_info: bool,
+    sent: HashMap<Query, Instant>,
+    round_winner: Option<String>,
+    log_map: Option<String>,
+    sessions: Sessions,
+    history: Option<Recorder>,
+    events: Option<broadcast::Sender<ServerEvent>>,
+    /// Unknown until the server replies or the up threshold passes since
+    /// the start, the first state isn't a change
+    up: Option<bool>,
+    started: Instant,
+    name: Option<String>,
     metrics: Arc<Metrics>,
 }
 
 impl GameServer {
     pub(crate) fn new(
-        server_addr: SocketAddr,
+        rx_addr: watch::Receiver<SocketAddr>,
+        options: ServerOptions,
         interval: Interval,
-        rx_challenge: Receiver<Vec<u8>>,
-        tx_challenge: Sender<Vec<u8>>,
         rx_packet: Receiver<Vec<u8>>,
+        rx_log: Receiver<LogEvent>,
         socket: Arc<UdpSocket>,
         metrics: Arc<Metrics>,
     ) -> Self {
+        let split = SplitPackets::new(options.reply_timeout);
+        let server_addr = *rx_addr.borrow();
+        let sessions = Sessions::new(options.session_player_limit);
         Self {
             server_addr,
+            rx_addr,
+            options,
             interval,
-            rx_challenge,
-            tx_challenge,
             rx_packet,
+            rx_log,
             socket,
 
             last_update: None,
             challenge: vec![],
+            split,
+            rcon_output: rcon::PrintBuffer::default(),
+            current_map: None,
+            obsolete_info: None,
+            current_info: false,
+            sent: HashMap::new(),
+            round_winner: None,
+            log_map: None,
+            sessions,
+            history: None,
+            events: None,
+            up: None,
+            started: Instant::now(),
+            name: None,
             metrics,
         }
     }
 
+    /// Records poll results in the history
+    pub(crate) fn with_history(mut self, history: Option<Recorder>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Publishes state changes of the server
+    pub(crate) fn with_events(
+        mut self,
+        events: Option<broadcast::Sender<ServerEvent>>,
+    ) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Server in history and events, hostname keeps it stable when its
+    /// address changes
+    fn server_label(&self) -> String {
+        self.options
+            .host
+            .clone()
+            .unwrap_or_else(|| self.server_addr.to_string())
+    }
+
+    fn emit(&self, event: impl FnOnce(String) -> ServerEvent) {
+        if let Some(events) = &self.events {
+            // Events are dropped while nobody is subscribed
+            events.send(event(self.server_label())).ok();
+        }
+    }
+
     pub(crate) async fn process(&mut self) {
         loop {
             select! {
                 _ = self.interval.tick() => {
-                    self.get_info().await.unwrap_or_else(|e| tracing::debug!("Error requesting info: {}", e));
-                    let up = self.last_update.map_or(false, |update| update.elapsed() < Duration::from_secs(5));
+                    self.flush_obsolete_info();
+                    for _ in 0..self.split.expire() {
+                        self.metrics.observe_split_failure(self.server_addr, SplitError::Timeout.reason());
+                    }
+                    self.expire_queries();
+                    self.query().await;
+                    let up = self.last_update.is_some_and(|update| update.elapsed() < self.options.up_threshold);
                     self.metrics.observe_up(self.server_addr, up);
+                    self.track_up(up);
+                    let unique = self.sessions.unique(&self.options.session_windows, Instant::now());
+                    self.metrics.observe_unique_players(self.server_addr, &unique);
+                }
+                Ok(()) = self.rx_addr.changed() => {
+                    self.server_addr = *self.rx_addr.borrow_and_update();
+                    // Challenge belongs to the previous address
+                    self.challenge.clear();
+                    self.sent.clear();
+                }
+                Some(packet) = self.rx_packet.recv() => {
+                    self.metrics.observe_reply(self.server_addr);
+                    self.parse_reply(&packet).await;
+                    self.last_update = Some(Instant::now());
                 }
-                Some(challenge) = self.rx_challenge.recv() => {
-                    self.challenge = challenge;
-                    self.get_info().await.unwrap_or_else(|e| tracing::debug!("Error requesting info: {}", e));
+                Some(event) = self.rx_log.recv() => {
+                    self.handle_log(event);
                 }
+            }
+        }
+    }
+
+    /// Emits an event when the server goes up or down
+    fn track_up(&mut self, up: bool) {
+        if !up && self.started.elapsed() < self.options.up_threshold {
+            return;
+        }
+        if self.up.replace(up).is_some_and(|previous| previous != up) {
+            self.emit(|server| {
+                if up {
+                    ServerEvent::Up { server }
+                } else {
+                    ServerEvent::Down { server }
+                }
+            });
+        }
+    }
+
+    /// Map from logs if they are received, otherwise from `A2S_INFO`
+    fn map_name(&self) -> String {
+        self.log_map
+            .clone()
+            .or_else(|| self.current_map.as_ref().map(|m| m.name.clone()))
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    fn handle_log(&mut self, event: LogEvent) {
+        self.metrics
+            .observe_log_event(self.server_addr, event.label());
+        if let LogEvent::MapStart(map) = &event {
+            let previous = self.map_name();
+            if previous != *map {
+                self.metrics.remove_map(self.server_addr, &previous);
+            }
+            self.log_map = Some(map.clone());
+        }
+        let map = self.map_name();
+        for cstrike_event in cstrike::events(&event) {
+            self.metrics.observe_cstrike_event(
+                self.server_addr,
+                &map,
+                &cstrike_event,
+            );
+        }
+        match event {
+            LogEvent::Connect(player) => {
+                self.metrics.observe_connect(self.server_addr);
+                if self.sessions.connect(&player, Instant::now()) {
+                    self.metrics.observe_sessions(self.server_addr, 1, &[]);
+                    self.emit(|server| ServerEvent::PlayerJoin {
+                        server,
+                        player: player.name.clone(),
+                    });
+                }
+            },
+            LogEvent::Disconnect(player) => {
+                self.metrics.observe_disconnect(self.server_addr);
+                // Players which joined before their sessions were tracked
+                // never had a join event
+                if let Some(duration) =
+                    self.sessions.disconnect(&player, Instant::now())
+                {
+                    self.metrics.observe_sessions(
+                        self.server_addr,
+                        0,
+                        &[duration],
+                    );
+                    self.emit(|server| ServerEvent::PlayerLeave {
+                        server,
+                        player: player.name.clone(),
+                    });
+                }
+            },
+            LogEvent::Kill {
+                weapon, headshot, ..
+            } => {
+                let weapon = self.options.weapon_label(&weapon);
+                self.metrics
+                    .observe_kill(self.server_addr, weapon, headshot);
+            },
+            LogEvent::Suicide { weapon, .. } => {
+                let weapon = self.options.weapon_label(&weapon);
+                self.metrics.observe_suicide(self.server_addr, weapon);
+            },
+            // Team which triggered an event last is the winner of the round
+            LogEvent::TeamTrigger { team, .. } => {
+                self.round_winner = Some(team);
+            },
+            LogEvent::RoundStart => self.round_winner = None,
+            LogEvent::RoundEnd => {
+                let winner = self.round_winner.take();
+                self.metrics.observe_round_end(self.server_addr, winner);
+            },
+            _ => {},
+        }
+    }
+
+    /// Queries the server once and waits for replies until the reply
+    /// timeout, returns whether all queries were answered
+    pub(crate) async fn probe(&mut self) -> bool {
+        let deadline = time::Instant::now() + self.options.reply_timeout;
+        self.query().await;
+        loop {
+            select! {
+                () = time::sleep_until(deadline) => break,
                 Some(packet) = self.rx_packet.recv() => {
+                    self.metrics.observe_reply(self.server_addr);
                     self.parse_reply(&packet).await;
                     self.last_update = Some(Instant::now());
+                    if self.sent.is_empty() {
+                        break;
+                    }
                 }
             }
         }
+        self.flush_obsolete_info();
+        let success = self.last_update.is_some() && self.sent.is_empty();
+        self.expire_queries();
+        self.metrics.observe_up(self.server_addr, success);
+        success
+    }
+
+    async fn query(&mut self) {
+        match self.get_info().await {
+            Ok(()) => self.mark_sent(Query::Info),
+            Err(e) => tracing::debug!("Error requesting info: {}", e),
+        }
+        if self.options.query_players {
+            match self.get_players().await {
+                Ok(()) => self.mark_sent(Query::Players),
+                Err(e) => tracing::debug!("Error requesting players: {}", e),
+            }
+        }
+        if self.options.query_rules() {
+            match self.get_rules().await {
+                Ok(()) => self.mark_sent(Query::Rules),
+                Err(e) => tracing::debug!("Error requesting rules: {}", e),
+            }
+        }
+        if self.options.rcon_password.is_some() {
+            if let Err(e) = self
+                .socket
+                .send_to(rcon::challenge_request(), self.server_addr)
+                .await
+            {
+                tracing::debug!("Error requesting rcon challenge: {}", e);
+            }
+        }
+    }
+
+    /// Sends queries which are still waiting for replies again with the
+    /// current challenge
+    async fn resend(&mut self) {
+        for query in [Query::Info, Query::Players, Query::Rules] {
+            if !self.sent.contains_key(&query) {
+                continue;
+            }
+            let result = match query {
+                Query::Info => self.get_info().await,
+                Query::Players => self.get_players().await,
+                Query::Rules => self.get_rules().await,
+            };
+            match result {
+                Ok(()) => {
+                    self.sent.insert(query, Instant::now());
+                },
+                Err(e) => tracing::debug!(
+                    "Error resending {} query: {}",
+                    query.label(),
+                    e
+                ),
+            }
+        }
+    }
+
+    fn mark_sent(&mut self, query: Query) {
+        self.metrics.observe_query_sent(self.server_addr, query);
+        self.sent.insert(query, Instant::now());
+    }
+
+    /// Counts queries left unanswered since the previous tick as timed out
+    fn expire_queries(&mut self) {
+        for (query, _) in self.sent.drain() {
+            self.metrics.observe_query_timeout(self.server_addr, query);
+        }
+    }
+
+    /// Observes round-trip time of the query answered by a reply
+    fn mark_received(&mut self, query: Query) {
+        if let Some(sent) = self.sent.remove(&query) {
+            let elapsed = sent.elapsed();
+            if elapsed > self.options.reply_timeout {
+                self.metrics.observe_query_timeout(self.server_addr, query);
+                return;
+            }
+            self.metrics.observe_query_duration(
+                self.server_addr,
+                query,
+                elapsed,
+            );
+        }
     }
 
     pub(crate) async fn get_info(&self) -> anyhow::Result<()> {
@@ -190,60 +609,402 @@ impl GameServer {
         Ok(())
     }
 
+    pub(crate) async fn get_players(&self) -> anyhow::Result<()> {
+        self.send_with_challenge(A2S_PLAYER).await
+    }
+
+    pub(crate) async fn get_rules(&self) -> anyhow::Result<()> {
+        self.send_with_challenge(A2S_RULES).await
+    }
+
+    async fn send_with_challenge(&self, request: &[u8]) -> anyhow::Result<()> {
+        let mut msg = Vec::from(request);
+        if self.challenge.is_empty() {
+            msg.extend(EMPTY_CHALLENGE);
+        } else {
+            msg.extend(&self.challenge);
+        }
+        self.socket.send_to(&msg, self.server_addr).await?;
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self, reply), fields(server = %self.server_addr))]
     pub async fn parse_reply(&mut self, reply: &[u8]) {
         if reply.starts_with(SPLIT_PACKET) {
-            tracing::warn!(server = %self.server_addr, "Split packet is not supported");
+            match self.split.push(reply) {
+                Ok(Some(packet)) => self.parse_packet(&packet).await,
+                Ok(None) => {},
+                Err(e) => {
+                    tracing::debug!(server = %self.server_addr, "Error reassembling split packet: {}", e);
+                    self.metrics
+                        .observe_split_failure(self.server_addr, e.reason());
+                },
+            }
             return;
         }
         self.parse_packet(reply).await;
     }
 
-    async fn parse_packet(&self, packet: &[u8]) {
+    async fn parse_packet(&mut self, packet: &[u8]) {
         if !packet.starts_with(HEADER) {
+            self.metrics
+                .observe_parse_error(self.server_addr, "invalid_header");
             return;
         }
 
         let Some(type_) = packet.get(HEADER.len()) else {
             tracing::warn!(server = %self.server_addr, "Packet without type is received");
+            self.metrics
+                .observe_parse_error(self.server_addr, "missing_type");
             return;
         };
 
         match *type_ {
-            S2A_INFO => {
+            S2A_INFO | S2A_INFO_OBSOLETE => {
+                self.mark_received(Query::Info);
                 self.parse_info(packet);
             },
+            S2A_PLAYER => {
+                self.mark_received(Query::Players);
+                self.parse_players(packet);
+            },
+            S2A_RULES => {
+                self.mark_received(Query::Rules);
+                self.parse_rules(packet);
+            },
             S2C_CHALLENGE => {
-                if let Ok(challenge) = Self::parse_challenge(packet) {
-                    let _ = self
-                        .tx_challenge
-                        .send(challenge)
-                        .await
-                        .inspect_err(|e| {
-                            tracing::warn!("Failed to send challenge: {e}");
-                        });
+                self.metrics.observe_challenge(self.server_addr);
+                match Self::parse_challenge(packet) {
+                    // Every query is answered with the same challenge,
+                    // pending ones were already sent again with it
+                    Ok(challenge) if challenge == self.challenge => {},
+                    Ok(challenge) => {
+                        self.challenge = challenge;
+                        self.resend().await;
+                    },
+                    Err(e) => {
+                        tracing::debug!("Error parsing challenge: {}", e);
+                        self.metrics.observe_parse_error(
+                            self.server_addr,
+                            "invalid_challenge",
+                        );
+                    },
                 }
             },
-            _ => {},
+            rcon::S2C_RCON_CHALLENGE => {
+                self.send_rcon(packet.get(HEADER.len()..).unwrap_or_default())
+                    .await;
+            },
+            rcon::A2A_PRINT => {
+                self.parse_print(
+                    packet.get(HEADER.len() + 1..).unwrap_or_default(),
+                );
+            },
+            _ => {
+                self.metrics
+                    .observe_parse_error(self.server_addr, "unknown_type");
+            },
         }
     }
 
-    fn parse_info(&self, packet: &[u8]) {
+    fn parse_info(&mut self, packet: &[u8]) {
         let buf = Cursor::new(packet);
-        let info = ServerInfo::try_from(buf);
-        if let Ok(info) = info {
-            tracing::trace!("{:?}", &info);
-            self.metrics.observe_players(
+        let info = match ServerInfo::try_from(buf) {
+            Ok(info) => info,
+            Err(e) => {
+                tracing::debug!("Error parsing info: {}", e);
+                self.metrics
+                    .observe_parse_error(self.server_addr, "invalid_info");
+                return;
+            },
+        };
+        tracing::trace!("{:?}", &info);
+        if info.header == S2A_INFO_OBSOLETE {
+            if let Some(mod_info) = &info.mod_info {
+                self.metrics.observe_mod_info(
+                    self.server_addr,
+                    mod_info.link.clone(),
+                    mod_info.download_link.clone(),
+                    mod_info.version,
+                    mod_info.size,
+                    mod_info.type_.label(),
+                    mod_info.dll.label(),
+                );
+            }
+            // Legacy servers may send a current reply along with the
+            // obsolete one, which is only observed if none comes
+            if !self.current_info {
+                self.obsolete_info = Some(info);
+            }
+            return;
+        }
+        self.current_info = true;
+        self.obsolete_info = None;
+        self.observe_info(info);
+    }
+
+    /// Observes an obsolete info reply which wasn't followed by a current
+    /// one
+    fn flush_obsolete_info(&mut self) {
+        if let Some(info) = self.obsolete_info.take() {
+            self.observe_info(info);
+        }
+    }
+
+    fn observe_info(&mut self, info: ServerInfo) {
+        if let Some(extra) = &info.extra {
+            self.metrics.observe_extra_info(
                 self.server_addr,
-                info.players,
-                info.bots,
+                extra.port,
+                extra.steam_id,
+                extra.keywords.clone(),
+                extra.game_id,
             );
-            self.metrics.observe_info(
+        }
+        self.metrics.observe_players(
+            self.server_addr,
+            info.players,
+            info.max_players,
+            info.bots,
+        );
+        self.metrics.observe_map(self.server_addr, info.map.clone());
+        self.metrics.observe_status(
+            self.server_addr,
+            InfoStatus {
+                name: info.name.clone(),
+                map: info.map.clone(),
+                game: info.game.clone(),
+                folder: info.folder.clone(),
+                version: info.version.clone(),
+                players: info.players,
+                max_players: info.max_players,
+                bots: info.bots,
+                vac: matches!(info.vac, Vac::Secured),
+                password: matches!(info.visibility, Visibility::Private),
+            },
+        );
+        if let Some(history) = &self.history {
+            history.record(Record::Info {
+                server: self.server_label(),
+                time: history::unix_time(),
+                name: info.name.clone(),
+                map: info.map.clone(),
+                players: info.players,
+                max_players: info.max_players,
+                bots: info.bots,
+            });
+        }
+        self.track_thresholds(info.players);
+        self.track_map(&info.map, info.players);
+        let previous = self.name.replace(info.name.clone());
+        if let Some(previous) = previous.filter(|name| *name != info.name) {
+            self.emit(|server| ServerEvent::NameChange {
+                server,
+                from: previous,
+                to: info.name.clone(),
+            });
+        }
+        self.metrics.observe_security(
+            self.server_addr,
+            matches!(info.vac, Vac::Secured),
+            matches!(info.visibility, Visibility::Private),
+        );
+        self.metrics.observe_details(
+            self.server_addr,
+            info.folder.clone(),
+            info.protocol,
+            info.environment.label(),
+            info.server_type.label(),
+        );
+        self.metrics.observe_info(
+            self.server_addr,
+            info.name,
+            info.game,
+            info.version,
+        );
+    }
+
+    /// Emits events when the player count crosses thresholds since the
+    /// previous info
+    fn track_thresholds(&self, players: u8) {
+        let Some(previous) = self.current_map.as_ref().map(|map| map.players)
+        else {
+            return;
+        };
+        for &threshold in &self.options.player_thresholds {
+            if previous < threshold && players >= threshold {
+                self.emit(|server| ServerEvent::PlayersAbove {
+                    server,
+                    threshold,
+                    players,
+                });
+            } else if previous >= threshold && players < threshold {
+                self.emit(|server| ServerEvent::PlayersBelow {
+                    server,
+                    threshold,
+                    players,
+                });
+            }
+        }
+    }
+
+    fn track_map(&mut self, map: &str, players: u8) {
+        let now = Instant::now();
+        match self.current_map.take() {
+            Some(previous) => {
+                let elapsed = now.duration_since(previous.observed);
+                if elapsed <= MAX_MAP_SAMPLE_GAP {
+                    self.metrics.observe_map_player_seconds(
+                        self.server_addr,
+                        previous.name.clone(),
+                        f64::from(previous.players) * elapsed.as_secs_f64(),
+                    );
+                }
+                if previous.name != map {
+                    tracing::debug!(server = %self.server_addr, "Map changed from {} to {}", previous.name, map);
+                    self.emit(|server| ServerEvent::MapChange {
+                        server,
+                        from: previous.name.clone(),
+                        to: map.to_string(),
+                    });
+                    self.metrics.observe_map_change(self.server_addr);
+                }
+            },
+            None => self.metrics.observe_map_start(self.server_addr),
+        }
+        self.current_map = Some(CurrentMap {
+            name: map.to_string(),
+            players,
+            observed: now,
+        });
+    }
+
+    /// Sends `stats` and `status` commands with the received challenge
+    async fn send_rcon(&self, payload: &[u8]) {
+        let Some(password) = &self.options.rcon_password else {
+            return;
+        };
+        let Some(challenge) = rcon::parse_challenge(payload) else {
+            self.metrics.observe_parse_error(
                 self.server_addr,
-                info.name,
-                info.game,
-                info.version,
+                "invalid_rcon_challenge",
             );
+            return;
+        };
+        for command in ["stats", "status"] {
+            let msg = rcon::command(&challenge, password.expose(), command);
+            if let Err(e) = self.socket.send_to(&msg, self.server_addr).await {
+                tracing::debug!("Error sending rcon {}: {}", command, e);
+            }
+        }
+    }
+
+    fn parse_print(&mut self, payload: &[u8]) {
+        let Some(text) = self.rcon_output.push(payload) else {
+            return;
+        };
+        match rcon::parse_print(text.as_bytes()) {
+            Ok(Reply::Stats(stats)) => {
+                self.metrics.observe_stats(self.server_addr, &stats);
+            },
+            Ok(Reply::Status(players)) => {
+                self.metrics
+                    .observe_player_pings(self.server_addr, &players);
+            },
+            Ok(Reply::Denied(message)) => {
+                tracing::warn!(server = %self.server_addr, "RCON command is rejected: {}", message);
+            },
+            Ok(Reply::Other) => {},
+            Err(e) => {
+                tracing::debug!("Error parsing rcon reply: {}", e);
+                self.metrics
+                    .observe_parse_error(self.server_addr, "invalid_rcon");
+            },
+        }
+    }
+
+    fn parse_players(&mut self, packet: &[u8]) {
+        if !self.options.query_players {
+            return;
+        }
+        let buf = Cursor::new(packet);
+        match PlayerList::try_from(buf) {
+            Ok(list) => {
+                tracing::trace!("{:?}", &list);
+                self.metrics
+                    .observe_player_list(self.server_addr, &list.players);
+                self.track_sessions(&list.players);
+            },
+            Err(e) => {
+                tracing::debug!("Error parsing player list: {}", e);
+                self.metrics
+                    .observe_parse_error(self.server_addr, "invalid_players");
+            },
+        }
+    }
+
+    fn track_sessions(&mut self, players: &[PlayerInfo]) {
+        // Players which are still connecting have no name yet
+        let players: Vec<_> = players
+            .iter()
+            .filter(|p| !p.name.is_empty())
+            .map(|p| {
+                let connected = Duration::try_from_secs_f32(p.duration)
+                    .unwrap_or_default();
+                (p.name.as_str(), connected)
+            })
+            .collect();
+        let changes = self.sessions.observe_list(&players, Instant::now());
+        let ended: Vec<_> =
+            changes.left.iter().map(|(_, duration)| *duration).collect();
+        self.metrics.observe_sessions(
+            self.server_addr,
+            changes.joined.len(),
+            &ended,
+        );
+        for player in changes.joined {
+            self.emit(|server| ServerEvent::PlayerJoin { server, player });
+        }
+        for (player, _) in changes.left {
+            self.emit(|server| ServerEvent::PlayerLeave { server, player });
+        }
+        if let Some(history) = &self.history {
+            let interval = self.options.interval + self.options.up_threshold;
+            history.record(Record::Players {
+                server: self.server_label(),
+                time: history::unix_time(),
+                players: players
+                    .iter()
+                    .map(|(name, connected)| {
+                        let connected = connected.as_secs();
+                        ((*name).to_owned(), connected.try_into().unwrap_or(0))
+                    })
+                    .collect(),
+                gap: interval.as_secs().try_into().unwrap_or(i64::MAX),
+            });
+        }
+    }
+
+    fn parse_rules(&self, packet: &[u8]) {
+        if !self.options.query_rules() {
+            return;
+        }
+        let buf = Cursor::new(packet);
+        match RuleList::try_from(buf) {
+            Ok(list) => {
+                tracing::trace!("{:?}", &list);
+                self.metrics.observe_rules(
+                    self.server_addr,
+                    &list.rules,
+                    &self.options.rule_gauges,
+                    &self.options.rule_labels,
+                );
+            },
+            Err(e) => {
+                tracing::debug!("Error parsing rules: {}", e);
+                self.metrics
+                    .observe_parse_error(self.server_addr, "invalid_rules");
+            },
         }
     }
 
@@ -265,6 +1026,9 @@ impl TryFrom<Cursor<&[u8]>> for ServerInfo {
         let mut value = value;
         let _packet_header = value.read_i32::<LittleEndian>()?;
         let header = value.read_u8()?;
+        if header == S2A_INFO_OBSOLETE {
+            return Self::parse_obsolete(header, value);
+        }
         let protocol = value.read_u8()?;
         let name = read_cstring(&mut value)?;
         let map = read_cstring(&mut value)?;
@@ -279,6 +1043,12 @@ impl TryFrom<Cursor<&[u8]>> for ServerInfo {
         let visibility = value.read_u8()?.try_into()?;
         let vac = value.read_u8()?.try_into()?;
         let version = read_cstring(&mut value)?;
+        let extra = if value.position() < u64::try_from(value.get_ref().len())?
+        {
+            Some(ExtraData::try_from(value)?)
+        } else {
+            None
+        };
 
         Ok(Self {
             header,
@@ -287,6 +1057,7 @@ impl TryFrom<Cursor<&[u8]>> for ServerInfo {
             map,
             folder,
             game,
+            address: None,
             id,
             players,
             max_players,
@@ -296,10 +1067,191 @@ impl TryFrom<Cursor<&[u8]>> for ServerInfo {
             visibility,
             vac,
             version,
+            extra,
+            mod_info: None,
+        })
+    }
+}
+
+impl ServerInfo {
+    fn parse_obsolete(
+        header: u8,
+        value: Cursor<&[u8]>,
+    ) -> anyhow::Result<Self> {
+        let mut value = value;
+        let address = read_cstring(&mut value)?;
+        let name = read_cstring(&mut value)?;
+        let map = read_cstring(&mut value)?;
+        let folder = read_cstring(&mut value)?;
+        let game = read_cstring(&mut value)?;
+        let players = value.read_u8()?;
+        let max_players = value.read_u8()?;
+        let protocol = value.read_u8()?;
+        let server_type = value.read_u8()?.try_into()?;
+        let environment = value.read_u8()?.try_into()?;
+        let visibility = value.read_u8()?.try_into()?;
+        let is_mod = value.read_u8()?;
+        let mod_info = if is_mod == 1 {
+            let link = read_cstring(&mut value)?;
+            let download_link = read_cstring(&mut value)?;
+            let _null = value.read_u8()?;
+            let version = value.read_i32::<LittleEndian>()?;
+            let size = value.read_i32::<LittleEndian>()?;
+            let type_ = value.read_u8()?.try_into()?;
+            let dll = value.read_u8()?.try_into()?;
+            Some(ModInfo {
+                link,
+                download_link,
+                version,
+                size,
+                type_,
+                dll,
+            })
+        } else {
+            None
+        };
+        let vac = value.read_u8()?.try_into()?;
+        let bots = value.read_u8()?;
+
+        Ok(Self {
+            header,
+            address: Some(address),
+            protocol,
+            name,
+            map,
+            folder,
+            game,
+            id: 0,
+            players,
+            max_players,
+            bots,
+            server_type,
+            environment,
+            visibility,
+            vac,
+            version: String::new(),
+            extra: None,
+            mod_info,
         })
     }
 }
 
+impl TryFrom<Cursor<&[u8]>> for ExtraData {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Cursor<&[u8]>) -> Result<Self, Self::Error> {
+        let mut value = value;
+        let flags = value.read_u8()?;
+        let mut extra = Self::default();
+        if flags & EDF_PORT != 0 {
+            extra.port = Some(value.read_u16::<LittleEndian>()?);
+        }
+        if flags & EDF_STEAM_ID != 0 {
+            extra.steam_id = Some(value.read_u64::<LittleEndian>()?);
+        }
+        if flags & EDF_SOURCE_TV != 0 {
+            extra.source_tv_port = Some(value.read_u16::<LittleEndian>()?);
+            extra.source_tv_name = Some(read_cstring(&mut value)?);
+        }
+        if flags & EDF_KEYWORDS != 0 {
+            extra.keywords = Some(read_cstring(&mut value)?);
+        }
+        if flags & EDF_GAME_ID != 0 {
+            extra.game_id = Some(value.read_u64::<LittleEndian>()?);
+        }
+
+        Ok(extra)
+    }
+}
+
+impl ServerType {
+    const fn label(&self) -> &'static str {
+        match self {
+            Self::Dedicated => "dedicated",
+            Self::Listen => "listen",
+            Self::Proxy => "proxy",
+        }
+    }
+}
+
+impl EnvironmentType {
+    const fn label(&self) -> &'static str {
+        match self {
+            Self::Linux => "linux",
+            Self::Windows => "windows",
+            Self::Mac => "mac",
+        }
+    }
+}
+
+impl ModType {
+    const fn label(&self) -> &'static str {
+        match self {
+            Self::SingleAndMultiplayer => "single_and_multiplayer",
+            Self::MultiplayerOnly => "multiplayer_only",
+        }
+    }
+}
+
+impl ModDll {
+    const fn label(&self) -> &'static str {
+        match self {
+            Self::HalfLife => "half_life",
+            Self::Own => "own",
+        }
+    }
+}
+
+impl TryFrom<Cursor<&[u8]>> for PlayerList {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Cursor<&[u8]>) -> Result<Self, Self::Error> {
+        let mut value = value;
+        let _packet_header = value.read_i32::<LittleEndian>()?;
+        let _header = value.read_u8()?;
+        let count = value.read_u8()?;
+        let mut players = Vec::with_capacity(count.into());
+        for _ in 0..count {
+            let index = value.read_u8()?;
+            let name = read_cstring(&mut value)?;
+            let score = value.read_i32::<LittleEndian>()?;
+            let duration = value.read_f32::<LittleEndian>()?;
+            players.push(PlayerInfo {
+                index,
+                name,
+                score,
+                duration,
+            });
+        }
+
+        Ok(Self { players })
+    }
+}
+
+impl TryFrom<Cursor<&[u8]>> for RuleList {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Cursor<&[u8]>) -> Result<Self, Self::Error> {
+        let mut value = value;
+        let end = u64::try_from(value.get_ref().len())?;
+        let _packet_header = value.read_i32::<LittleEndian>()?;
+        let _header = value.read_u8()?;
+        let count = value.read_i16::<LittleEndian>()?;
+        let mut rules = Vec::with_capacity(count.try_into().unwrap_or(0));
+        // Some servers announce more rules than they fit in the reply
+        for _ in 0..count {
+            if value.position() >= end {
+                break;
+            }
+            let name = read_cstring(&mut value)?;
+            let rule = read_cstring(&mut value)?;
+            rules.push((name, rule));
+        }
+
+        Ok(Self { rules })
+    }
+}
+
 fn read_cstring(buf: &mut Cursor<&[u8]>) -> anyhow::Result<String> {
     let end = buf.get_ref().len().try_into()?;
     let mut c = [0; 1];
@@ -315,3 +1267,446 @@ fn read_cstring(buf: &mut Cursor<&[u8]>) -> anyhow::Result<String> {
 
     Ok(String::from_utf8_lossy(str_vec.as_slice()).to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::net::UdpSocket;
+    use tokio::sync::{broadcast, mpsc, watch};
+    use tokio::time;
+
+    use super::{
+        GameServer, PlayerInfo, PlayerList, Query, RuleList, ServerInfo,
+        MAX_MAP_SAMPLE_GAP,
+    };
+    use crate::config::ServerOptions;
+    use crate::logs;
+    use crate::metrics::Metrics;
+
+    fn info_packet() -> Vec<u8> {
+        let mut packet = b"\xFF\xFF\xFF\xFF\x49\x30".to_vec();
+        packet.extend(b"Server\0de_dust2\0cstrike\0Counter-Strike\0");
+        packet.extend(b"\x0A\x00\x05\x20\x02dl\x01\x011.1.2.7/Stdio\0");
+        packet
+    }
+
+    fn obsolete_info_packet() -> Vec<u8> {
+        let mut packet = b"\xFF\xFF\xFF\xFF\x6D127.0.0.1:27015\0".to_vec();
+        packet.extend(b"Server\0de_dust2\0cstrike\0Counter-Strike\0");
+        packet.extend(b"\x05\x20\x2FDL\x00\x01");
+        packet.extend(b"http://link\0http://download\0\0");
+        packet.extend(1_i32.to_le_bytes());
+        packet.extend(184_000_000_i32.to_le_bytes());
+        packet.extend(b"\x01\x01\x01\x02");
+        packet
+    }
+
+    /// Worker of a server at `127.0.0.1:27015` which replies are passed
+    /// to it directly
+    async fn game_server(
+        options: ServerOptions,
+    ) -> (GameServer, Arc<Metrics>) {
+        let addr: SocketAddr = "127.0.0.1:27015".parse().unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let metrics = Arc::new(Metrics::new(String::new(), &[0.1, 1.0]));
+        let server = GameServer::new(
+            watch::channel(addr).1,
+            options,
+            time::interval(Duration::from_secs(5)),
+            mpsc::channel(1).1,
+            mpsc::channel(1).1,
+            socket,
+            Arc::clone(&metrics),
+        );
+        (server, metrics)
+    }
+
+    /// Value of the first sample which line starts with `prefix`
+    fn sample(text: &str, prefix: &str) -> Option<f64> {
+        text.lines()
+            .find(|line| line.starts_with(prefix))
+            .and_then(|line| line.rsplit_once(' '))
+            .and_then(|(_, value)| value.parse().ok())
+    }
+
+    /// Moves the last observation of the current map into the past
+    fn age_current_map(server: &mut GameServer, age: u64) {
+        let map = server.current_map.as_mut().expect("map should be known");
+        map.observed -= Duration::from_secs(age);
+    }
+
+    #[tokio::test]
+    async fn track_map_changes() {
+        let (mut server, metrics) =
+            game_server(ServerOptions::default()).await;
+        let start = "hlds_current_map_start_timestamp_seconds{";
+        let changes = "hlds_map_changes_total{";
+        let dust2 = "hlds_map_player_seconds_total{addr=\"127.0.0.1:27015\",map=\"de_dust2\"}";
+
+        server.track_map("de_dust2", 4);
+        let text = metrics.encode();
+        let started = sample(&text, start).expect("start should be set");
+        assert!(started > 0.0);
+        assert_eq!(sample(&text, changes), None);
+        assert_eq!(sample(&text, dust2), None);
+
+        age_current_map(&mut server, 10);
+        server.track_map("de_dust2", 6);
+        let text = metrics.encode();
+        assert_eq!(sample(&text, changes), None);
+        let seconds = sample(&text, dust2).expect("seconds should be counted");
+        assert!((40.0..41.0).contains(&seconds), "{seconds}");
+
+        age_current_map(&mut server, 10);
+        server.track_map("de_inferno", 2);
+        let text = metrics.encode();
+        assert_eq!(sample(&text, changes), Some(1.0));
+        assert!(sample(&text, start) >= Some(started));
+        let seconds = sample(&text, dust2).expect("seconds should be counted");
+        assert!((100.0..101.0).contains(&seconds), "{seconds}");
+    }
+
+    #[tokio::test]
+    async fn skip_player_seconds_after_gap() {
+        let (mut server, metrics) =
+            game_server(ServerOptions::default()).await;
+        let inferno = "hlds_map_player_seconds_total{addr=\"127.0.0.1:27015\",map=\"de_inferno\"}";
+
+        server.track_map("de_inferno", 2);
+        age_current_map(&mut server, MAX_MAP_SAMPLE_GAP.as_secs() + 1);
+        server.track_map("de_inferno", 2);
+        assert_eq!(sample(&metrics.encode(), inferno), None);
+
+        age_current_map(&mut server, MAX_MAP_SAMPLE_GAP.as_secs() - 1);
+        server.track_map("de_inferno", 2);
+        let text = metrics.encode();
+        let seconds =
+            sample(&text, inferno).expect("seconds should be counted");
+        assert!((28.0..29.0).contains(&seconds), "{seconds}");
+    }
+
+    fn query_options() -> ServerOptions {
+        ServerOptions {
+            reply_timeout: Duration::from_secs(5),
+            ..ServerOptions::default()
+        }
+    }
+
+    /// Moves the time a pending query was sent into the past
+    fn age_query(server: &mut GameServer, query: Query, age: u64) {
+        let sent = server.sent.get_mut(&query).expect("query should be sent");
+        *sent -= Duration::from_secs(age);
+    }
+
+    #[tokio::test]
+    async fn observe_query_duration() {
+        let (mut server, metrics) = game_server(query_options()).await;
+        let labels = "{addr=\"127.0.0.1:27015\",query=\"info\"}";
+        let count = format!("hlds_query_duration_seconds_count{labels}");
+        let timeouts = format!("hlds_query_timeouts_total{labels}");
+
+        server.mark_sent(Query::Info);
+        server.parse_reply(&info_packet()).await;
+        let text = metrics.encode();
+        assert_eq!(sample(&text, &count), Some(1.0));
+        let fast = "hlds_query_duration_seconds_bucket{le=\"0.1\",";
+        assert_eq!(sample(&text, fast), Some(1.0), "{text}");
+        assert_eq!(sample(&text, &timeouts), None);
+
+        // Replies after the reply timeout are counted as timeouts only
+        server.mark_sent(Query::Info);
+        age_query(&mut server, Query::Info, 6);
+        server.parse_reply(&info_packet()).await;
+        let text = metrics.encode();
+        assert_eq!(sample(&text, &count), Some(1.0));
+        assert_eq!(sample(&text, &timeouts), Some(1.0));
+
+        // Replies which weren't asked for aren't observed
+        server.parse_reply(&info_packet()).await;
+        assert_eq!(sample(&metrics.encode(), &count), Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn count_query_timeouts() {
+        let (mut server, metrics) = game_server(query_options()).await;
+        let addr = "addr=\"127.0.0.1:27015\"";
+
+        server.mark_sent(Query::Info);
+        server.mark_sent(Query::Players);
+        server.parse_reply(&info_packet()).await;
+        server.expire_queries();
+        let text = metrics.encode();
+        let info =
+            format!("hlds_query_timeouts_total{{{addr},query=\"info\"}}");
+        let players =
+            format!("hlds_query_timeouts_total{{{addr},query=\"players\"}}");
+        assert_eq!(sample(&text, &info), None);
+        assert_eq!(sample(&text, &players), Some(1.0));
+        assert!(server.sent.is_empty());
+
+        // Expired queries aren't counted again
+        server.expire_queries();
+        assert_eq!(sample(&metrics.encode(), &players), Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn resend_queries_on_challenge() {
+        let (mut server, metrics) = game_server(query_options()).await;
+        let addr = "addr=\"127.0.0.1:27015\"";
+        let sent = format!("hlds_queries_sent_total{{{addr},query=\"info\"}}");
+        let challenges = format!("hlds_challenges_received_total{{{addr}}}");
+        let challenge = b"\xFF\xFF\xFF\xFF\x41\x01\x02\x03\x04";
+
+        server.mark_sent(Query::Info);
+        age_query(&mut server, Query::Info, 2);
+        server.parse_reply(challenge).await;
+        assert_eq!(server.challenge, b"\x01\x02\x03\x04");
+        // Round-trip time is measured from the resend
+        let resent = server.sent.get(&Query::Info).expect("query is pending");
+        assert!(resent.elapsed() < Duration::from_secs(1));
+
+        // The same challenge again doesn't trigger another resend
+        age_query(&mut server, Query::Info, 2);
+        server.parse_reply(challenge).await;
+        let pending = server.sent.get(&Query::Info).expect("query is pending");
+        assert!(pending.elapsed() >= Duration::from_secs(2));
+
+        let text = metrics.encode();
+        assert_eq!(sample(&text, &sent), Some(1.0));
+        assert_eq!(sample(&text, &challenges), Some(2.0));
+    }
+
+    fn handle_log(server: &mut GameServer, message: &str) {
+        let line = format!("L 10/17/2026 - 12:00:00: {message}");
+        server.handle_log(logs::parse_line(&line).expect("line should parse"));
+    }
+
+    #[tokio::test]
+    async fn remove_team_scores_of_previous_map() {
+        let (mut server, metrics) =
+            game_server(ServerOptions::default()).await;
+        let score = "hlds_cstrike_team_score{addr=\"127.0.0.1:27015\",map=";
+        let purchases =
+            "hlds_cstrike_purchases_total{addr=\"127.0.0.1:27015\",map=";
+
+        handle_log(&mut server, r#"Started map "de_dust2" (CRC "-1234")"#);
+        handle_log(&mut server, r#"Team "CT" scored "3" with "5" players"#);
+        handle_log(
+            &mut server,
+            r#""alice<2><STEAM_0:1:2><CT>" purchased "m4a1""#,
+        );
+        let text = metrics.encode();
+        assert_eq!(
+            sample(&text, &format!("{score}\"de_dust2\",team=\"CT\"}}")),
+            Some(3.0)
+        );
+        assert_eq!(
+            sample(&text, &format!("{purchases}\"de_dust2\",item=\"m4a1\"}}")),
+            Some(1.0)
+        );
+
+        handle_log(&mut server, r#"Started map "de_inferno" (CRC "-1234")"#);
+        handle_log(&mut server, r#"Team "CT" scored "0" with "5" players"#);
+        let text = metrics.encode();
+        assert_eq!(sample(&text, &format!("{score}\"de_dust2\"")), None);
+        assert_eq!(sample(&text, &format!("{purchases}\"de_dust2\"")), None);
+        assert_eq!(
+            sample(&text, &format!("{score}\"de_inferno\",team=\"CT\"}}")),
+            Some(0.0)
+        );
+    }
+
+    #[tokio::test]
+    async fn emit_leave_of_known_players() {
+        let options = ServerOptions {
+            session_player_limit: 10,
+            ..ServerOptions::default()
+        };
+        let (server, _) = game_server(options).await;
+        let (tx, mut rx) = broadcast::channel(8);
+        let mut server = server.with_events(Some(tx));
+
+        handle_log(&mut server, r#""bob<3><STEAM_0:1:3><CT>" disconnected"#);
+        handle_log(
+            &mut server,
+            r#""alice<2><STEAM_0:1:2><>" connected, address "10.0.0.5:27005""#,
+        );
+        handle_log(&mut server, r#""alice<2><STEAM_0:1:2><CT>" disconnected"#);
+        let labels: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|event| event.label())
+            .collect();
+        assert_eq!(labels, ["player_join", "player_leave"]);
+    }
+
+    fn rules_packet(rules: &[(&str, &str)]) -> Vec<u8> {
+        let mut packet = b"\xFF\xFF\xFF\xFF\x45".to_vec();
+        packet.extend(u16::try_from(rules.len()).unwrap().to_le_bytes());
+        for (name, value) in rules {
+            packet.extend(format!("{name}\0{value}\0").bytes());
+        }
+        packet
+    }
+
+    #[tokio::test]
+    async fn remove_missing_rule_gauges() {
+        let options = ServerOptions {
+            rule_gauges: vec![
+                "mp_timelimit".to_owned(),
+                "sv_gravity".to_owned(),
+            ],
+            ..ServerOptions::default()
+        };
+        let (mut server, metrics) = game_server(options).await;
+        let rule = "hlds_rule{addr=\"127.0.0.1:27015\",name=";
+        let gravity = format!("{rule}\"sv_gravity\"}}");
+
+        server
+            .parse_reply(&rules_packet(&[
+                ("mp_timelimit", "20"),
+                ("sv_gravity", "800"),
+            ]))
+            .await;
+        assert_eq!(sample(&metrics.encode(), &gravity), Some(800.0));
+
+        server
+            .parse_reply(&rules_packet(&[("mp_timelimit", "30")]))
+            .await;
+        let text = metrics.encode();
+        assert_eq!(sample(&text, &gravity), None);
+        assert_eq!(
+            sample(&text, &format!("{rule}\"mp_timelimit\"}}")),
+            Some(30.0)
+        );
+    }
+
+    #[tokio::test]
+    async fn observe_obsolete_info_only() {
+        let (mut server, metrics) =
+            game_server(ServerOptions::default()).await;
+        server.parse_reply(&obsolete_info_packet()).await;
+        server.flush_obsolete_info();
+
+        let text = metrics.encode();
+        assert!(text.contains("hlds_players{addr=\"127.0.0.1:27015\"} 5"));
+        assert!(text.contains("hlds_bots{addr=\"127.0.0.1:27015\"} 2"));
+        assert!(text.contains("map=\"de_dust2\"} 1"));
+        assert!(text
+            .contains("hlds_info{addr=\"127.0.0.1:27015\",name=\"Server\""));
+        assert!(text.contains("download_link=\"http://download\""));
+    }
+
+    #[tokio::test]
+    async fn prefer_current_info_over_obsolete() {
+        let (mut server, metrics) =
+            game_server(ServerOptions::default()).await;
+        server.parse_reply(&obsolete_info_packet()).await;
+        server.parse_reply(&info_packet()).await;
+        server.flush_obsolete_info();
+        // Obsolete replies of later ticks only add mod info
+        server.parse_reply(&obsolete_info_packet()).await;
+        server.flush_obsolete_info();
+
+        let text = metrics.encode();
+        assert_eq!(text.matches("hlds_info{").count(), 1, "{text}");
+        assert!(text.contains("version=\"1.1.2.7/Stdio\""));
+        assert!(text.contains("download_link=\"http://download\""));
+    }
+
+    #[test]
+    fn parse_player_list() {
+        let mut packet = b"\xFF\xFF\xFF\xFF\x44\x02".to_vec();
+        packet.extend(b"\x00player\0");
+        packet.extend(7_i32.to_le_bytes());
+        packet.extend(61.5_f32.to_le_bytes());
+        packet.extend(b"\x01\0");
+        packet.extend((-1_i32).to_le_bytes());
+        packet.extend(2.0_f32.to_le_bytes());
+
+        let list = PlayerList::try_from(Cursor::new(packet.as_slice()))
+            .expect("player list should parse");
+        assert_eq!(
+            list.players,
+            vec![
+                PlayerInfo {
+                    index: 0,
+                    name: "player".to_string(),
+                    score: 7,
+                    duration: 61.5,
+                },
+                PlayerInfo {
+                    index: 1,
+                    name: String::new(),
+                    score: -1,
+                    duration: 2.0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_truncated_player_list() {
+        let packet = b"\xFF\xFF\xFF\xFF\x44\x01\x00player\0";
+        assert!(PlayerList::try_from(Cursor::new(&packet[..])).is_err());
+    }
+
+    #[test]
+    fn parse_rule_list() {
+        let packet =
+            b"\xFF\xFF\xFF\xFF\x45\x03\x00mp_timelimit\x0020\0sv_gravity\x00800\0";
+        let list = RuleList::try_from(Cursor::new(&packet[..]))
+            .expect("rule list should parse");
+        assert_eq!(
+            list.rules,
+            vec![
+                ("mp_timelimit".to_string(), "20".to_string()),
+                ("sv_gravity".to_string(), "800".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_obsolete_info() {
+        let packet = obsolete_info_packet();
+
+        let info = ServerInfo::try_from(Cursor::new(packet.as_slice()))
+            .expect("obsolete info should parse");
+        assert_eq!(info.address.as_deref(), Some("127.0.0.1:27015"));
+        assert_eq!(info.map, "de_dust2");
+        assert_eq!((info.players, info.max_players, info.bots), (5, 32, 2));
+        let mod_info = info.mod_info.expect("mod info should be present");
+        assert_eq!(mod_info.download_link, "http://download");
+        assert_eq!(mod_info.size, 184_000_000);
+    }
+
+    #[test]
+    fn parse_info_without_edf() {
+        let packet = info_packet();
+        let info = ServerInfo::try_from(Cursor::new(packet.as_slice()))
+            .expect("info should parse");
+        assert_eq!(info.version, "1.1.2.7/Stdio");
+        assert!(info.extra.is_none());
+    }
+
+    #[test]
+    fn parse_info_with_edf() {
+        let mut packet = info_packet();
+        packet.push(0x80 | 0x10 | 0x20 | 0x01);
+        packet.extend(27016_u16.to_le_bytes());
+        packet.extend(90_071_992_547_409_920_u64.to_le_bytes());
+        packet.extend(b"secure,dust\0");
+        packet.extend(10_u64.to_le_bytes());
+
+        let info = ServerInfo::try_from(Cursor::new(packet.as_slice()))
+            .expect("info should parse");
+        let extra = info.extra.expect("extra data should be present");
+        assert_eq!(extra.port, Some(27016));
+        assert_eq!(extra.steam_id, Some(90_071_992_547_409_920));
+        assert_eq!(extra.source_tv_port, None);
+        assert_eq!(extra.keywords.as_deref(), Some("secure,dust"));
+        assert_eq!(extra.game_id, Some(10));
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::BTreeMap;
+
+static LOG_HEADER: &[u8] = b"\xFF\xFF\xFF\xFFlog ";
+
+/// Player as printed in log lines: `Name<uid><STEAM_ID><TEAM>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Player {
+    pub name: String,
+    pub uid: String,
+    pub steam_id: String,
+    pub team: String,
+}
+
+/// Event of the standard HL log format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogEvent {
+    Connect(Player),
+    Disconnect(Player),
+    Kill {
+        killer: Player,
+        victim: Player,
+        weapon: String,
+        headshot: bool,
+    },
+    Suicide {
+        player: Player,
+        weapon: String,
+    },
+    TeamJoin {
+        player: Player,
+        team: String,
+    },
+    Say {
+        player: Player,
+        team_only: bool,
+    },
+    PlayerTrigger {
+        player: Player,
+        trigger: String,
+    },
+    TeamTrigger {
+        team: String,
+        trigger: String,
+        properties: BTreeMap<String, String>,
+    },
+    TeamScore {
+        team: String,
+        score: i64,
+    },
+    Purchase {
+        player: Player,
+        item: String,
+    },
+    MapStart(String),
+    RoundStart,
+    RoundEnd,
+    WorldTrigger(String),
+}
+
+impl LogEvent {
+    pub const fn label(&self) -> &'static str {
+        match self {
+            Self::Connect(_) => "connect",
+            Self::Disconnect(_) => "disconnect",
+            Self::Kill { .. } => "kill",
+            Self::Suicide { .. } => "suicide",
+            Self::TeamJoin { .. } => "team_join",
+            Self::Say { .. } => "say",
+            Self::PlayerTrigger { .. } => "player_trigger",
+            Self::TeamTrigger { .. } => "team_trigger",
+            Self::TeamScore { .. } => "team_score",
+            Self::Purchase { .. } => "purchase",
+            Self::MapStart(_) => "map_start",
+            Self::RoundStart => "round_start",
+            Self::RoundEnd => "round_end",
+            Self::WorldTrigger(_) => "world_trigger",
+        }
+    }
+}
+
+/// Extracts the log line from a `logaddress_add` packet
+pub fn parse_packet(packet: &[u8]) -> Option<String> {
+    let line = packet.strip_prefix(LOG_HEADER)?;
+    let line = String::from_utf8_lossy(line);
+    Some(line.trim_end_matches(['\0', '\n', '\r']).to_owned())
+}
+
+/// Parses `L 10/17/2026 - 12:00:00: <message>` lines, unknown messages
+/// are ignored
+pub fn parse_line(line: &str) -> Option<LogEvent> {
+    let (_timestamp, message) = line.strip_prefix("L ")?.split_once(": ")?;
+    parse_message(message)
+}
+
+fn parse_message(message: &str) -> Option<LogEvent> {
+    if let Some(rest) = message.strip_prefix("Started map ") {
+        return Some(LogEvent::MapStart(quoted(rest)?.0.to_owned()));
+    }
+    if let Some(rest) = message.strip_prefix("World triggered ") {
+        return Some(match quoted(rest)?.0 {
+            "Round_Start" => LogEvent::RoundStart,
+            "Round_End" => LogEvent::RoundEnd,
+            trigger => LogEvent::WorldTrigger(trigger.to_owned()),
+        });
+    }
+    if let Some(rest) = message.strip_prefix("Team ") {
+        let (team, rest) = quoted(rest)?;
+        if let Some(rest) = rest.strip_prefix(" scored ") {
+            return Some(LogEvent::TeamScore {
+                team: team.to_owned(),
+                score: quoted(rest)?.0.parse().ok()?,
+            });
+        }
+        let (trigger, rest) = quoted(rest.strip_prefix(" triggered ")?)?;
+        return Some(LogEvent::TeamTrigger {
+            team: team.to_owned(),
+            trigger: trigger.to_owned(),
+            properties: properties(rest),
+        });
+    }
+
+    let (player, rest) = player_prefix(message)?;
+    if rest.starts_with("connected") {
+        return Some(LogEvent::Connect(player));
+    }
+    if rest.starts_with("disconnected") {
+        return Some(LogEvent::Disconnect(player));
+    }
+    if let Some(rest) = rest.strip_prefix("killed ") {
+        let (victim, rest) = player_prefix(rest)?;
+        let (weapon, rest) = quoted(rest.strip_prefix("with ")?)?;
+        return Some(LogEvent::Kill {
+            killer: player,
+            victim,
+            weapon: weapon.to_owned(),
+            headshot: properties(rest).contains_key("headshot"),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("committed suicide with ") {
+        return Some(LogEvent::Suicide {
+            player,
+            weapon: quoted(rest)?.0.to_owned(),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("joined team ") {
+        return Some(LogEvent::TeamJoin {
+            player,
+            team: quoted(rest)?.0.to_owned(),
+        });
+    }
+    if rest.starts_with("say_team ") {
+        return Some(LogEvent::Say {
+            player,
+            team_only: true,
+        });
+    }
+    if rest.starts_with("say ") {
+        return Some(LogEvent::Say {
+            player,
+            team_only: false,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("purchased ") {
+        return Some(LogEvent::Purchase {
+            player,
+            item: quoted(rest)?.0.to_owned(),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("triggered ") {
+        return Some(LogEvent::PlayerTrigger {
+            player,
+            trigger: quoted(rest)?.0.to_owned(),
+        });
+    }
+    None
+}
+
+/// Splits `"value" rest` into the value and the rest
+fn quoted(text: &str) -> Option<(&str, &str)> {
+    let text = text.strip_prefix('"')?;
+    let (value, rest) = text.split_once('"')?;
+    Some((value, rest))
+}
+
+/// Parses trailing properties like `(CT "3") (headshot)`, properties
+/// without a value are kept with an empty one
+fn properties(text: &str) -> BTreeMap<String, String> {
+    let mut properties = BTreeMap::new();
+    let mut rest = text.trim_start();
+    while let Some(property) = rest.strip_prefix('(') {
+        let Some((property, tail)) = property.split_once(')') else {
+            break;
+        };
+        let (key, value) = property.split_once(' ').unwrap_or((property, ""));
+        let value = value.trim().trim_matches('"');
+        properties.insert(key.to_owned(), value.to_owned());
+        rest = tail.trim_start();
+    }
+    properties
+}
+
+/// Splits `"Name<uid><STEAM_ID><TEAM>" rest` into the player and the rest
+fn player_prefix(text: &str) -> Option<(Player, &str)> {
+    let text = text.strip_prefix('"')?;
+    let end = text.find(">\" ")?;
+    let player = parse_player(text.get(..=end)?)?;
+    Some((player, text.get(end + 3..)?))
+}
+
+fn parse_player(text: &str) -> Option<Player> {
+    let (rest, team) = text.strip_suffix('>')?.rsplit_once('<')?;
+    let (rest, steam_id) = rest.strip_suffix('>')?.rsplit_once('<')?;
+    let (name, uid) = rest.strip_suffix('>')?.rsplit_once('<')?;
+    Some(Player {
+        name: name.to_owned(),
+        uid: uid.to_owned(),
+        steam_id: steam_id.to_owned(),
+        team: team.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_line, parse_packet, LogEvent, Player};
+
+    fn player(name: &str, uid: &str, team: &str) -> Player {
+        Player {
+            name: name.to_owned(),
+            uid: uid.to_owned(),
+            steam_id: format!("STEAM_0:1:{uid}"),
+            team: team.to_owned(),
+        }
+    }
+
+    #[test]
+    fn parse_log_packet() {
+        let line = parse_packet(
+            b"\xFF\xFF\xFF\xFFlog L 10/17/2026 - 12:00:00: World triggered \"Round_Start\"\n\0",
+        )
+        .expect("packet should parse");
+        assert_eq!(
+            line,
+            "L 10/17/2026 - 12:00:00: World triggered \"Round_Start\""
+        );
+        assert_eq!(parse_line(&line), Some(LogEvent::RoundStart));
+        assert_eq!(parse_packet(b"\xFF\xFF\xFF\xFF\x49"), None);
+    }
+
+    #[test]
+    fn parse_player_events() {
+        let line = |message: &str| {
+            parse_line(&format!("L 10/17/2026 - 12:00:00: {message}"))
+        };
+        assert_eq!(
+            line(
+                r#""a<b> c<2><STEAM_0:1:2><>" connected, address "10.0.0.1:27005""#
+            ),
+            Some(LogEvent::Connect(player("a<b> c", "2", "")))
+        );
+        assert_eq!(
+            line(
+                r#""alice<2><STEAM_0:1:2><CT>" killed "bob<3><STEAM_0:1:3><TERRORIST>" with "ak47""#
+            ),
+            Some(LogEvent::Kill {
+                killer: player("alice", "2", "CT"),
+                victim: player("bob", "3", "TERRORIST"),
+                weapon: "ak47".to_owned(),
+                headshot: false,
+            })
+        );
+        assert_eq!(
+            line(
+                r#""alice<2><STEAM_0:1:2><CT>" killed "bob<3><STEAM_0:1:3><TERRORIST>" with "deagle" (headshot)"#
+            ),
+            Some(LogEvent::Kill {
+                killer: player("alice", "2", "CT"),
+                victim: player("bob", "3", "TERRORIST"),
+                weapon: "deagle".to_owned(),
+                headshot: true,
+            })
+        );
+        assert_eq!(
+            line(
+                r#""bob<3><STEAM_0:1:3><TERRORIST>" committed suicide with "worldspawn" (world)"#
+            ),
+            Some(LogEvent::Suicide {
+                player: player("bob", "3", "TERRORIST"),
+                weapon: "worldspawn".to_owned(),
+            })
+        );
+        assert_eq!(
+            line(r#""bob<3><STEAM_0:1:3><>" joined team "TERRORIST""#),
+            Some(LogEvent::TeamJoin {
+                player: player("bob", "3", ""),
+                team: "TERRORIST".to_owned(),
+            })
+        );
+        assert_eq!(
+            line(r#""bob<3><STEAM_0:1:3><TERRORIST>" say_team "rush b""#),
+            Some(LogEvent::Say {
+                player: player("bob", "3", "TERRORIST"),
+                team_only: true,
+            })
+        );
+        assert_eq!(
+            line(r#""bob<3><STEAM_0:1:3><TERRORIST>" disconnected"#),
+            Some(LogEvent::Disconnect(player("bob", "3", "TERRORIST")))
+        );
+    }
+
+    #[test]
+    fn parse_world_events() {
+        let line = |message: &str| {
+            parse_line(&format!("L 10/17/2026 - 12:00:00: {message}"))
+        };
+        assert_eq!(
+            line(r#"Started map "de_dust2" (CRC "-1234")"#),
+            Some(LogEvent::MapStart("de_dust2".to_owned()))
+        );
+        assert_eq!(
+            line(r#"Team "CT" triggered "CTs_Win" (CT "3") (T "1")"#),
+            Some(LogEvent::TeamTrigger {
+                team: "CT".to_owned(),
+                trigger: "CTs_Win".to_owned(),
+                properties: [("CT", "3"), ("T", "1")]
+                    .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                    .into(),
+            })
+        );
+        assert_eq!(
+            line(r#"World triggered "Round_End""#),
+            Some(LogEvent::RoundEnd)
+        );
+        assert_eq!(
+            line(r#"Team "TERRORIST" scored "7" with "5" players"#),
+            Some(LogEvent::TeamScore {
+                team: "TERRORIST".to_owned(),
+                score: 7,
+            })
+        );
+        assert_eq!(line("Server cvars start"), None);
+    }
+}
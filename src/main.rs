@@ -1,15 +1,26 @@
+mod api;
 mod config;
+mod cstrike;
+mod discovery;
+mod events;
+mod file_sd;
+mod history;
 mod hlds;
+mod logs;
 mod metrics;
+mod probe;
+mod rcon;
+mod servers;
+mod sessions;
+mod split;
+mod webhooks;
 
-use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
-use std::vec;
 
 use clap::Parser;
 use dotenvy::dotenv;
 use tokio::net::UdpSocket;
+use tokio::select;
 use tokio::sync::mpsc;
 use tokio::time;
 use tracing::level_filters::LevelFilter;
@@ -18,14 +29,22 @@ use tracing_subscriber::EnvFilter;
 use config::Config;
 use config::LogFormat;
 use config::LogLevel;
+use config::ModuleSpec;
+use config::ServerSpec;
+use discovery::Discovery;
+use events::Events;
+use file_sd::FileSd;
+use history::History;
 use hlds::MAX_REPLY_SIZE;
+use probe::Prober;
+use servers::{Routes, Servers};
+use webhooks::Webhooks;
 
 fn setup_logger(log_level: LogLevel, log_format: LogFormat) {
     let log_level: LevelFilter = log_level.into();
 
     let with_color = supports_color::on(supports_color::Stream::Stderr)
-        .filter(|s| s.has_basic)
-        .is_some();
+        .is_some_and(|s| s.has_basic);
     let filter = EnvFilter::builder()
         .with_default_directive(
             format!(
@@ -48,6 +67,86 @@ fn setup_logger(log_level: LogLevel, log_format: LogFormat) {
     }
 }
 
+#[cfg(unix)]
+fn forward_hangup(reload: mpsc::Sender<()>) -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            let _ = reload.try_send(());
+        }
+    });
+    Ok(())
+}
+
+/// Passes replies to workers of the servers which sent them
+#[allow(clippy::infinite_loop)]
+async fn read_replies(socket: Arc<UdpSocket>, routes: Routes) {
+    let mut buf = [0; MAX_REPLY_SIZE];
+    loop {
+        let Ok((amt, src)) = socket.recv_from(&mut buf).await else {
+            tracing::warn!("Error reading from socket");
+            continue;
+        };
+        let channel = routes.read().ok().and_then(|routes| {
+            routes.get(&src).map(|route| route.packets.clone())
+        });
+        if let Some(c) = channel {
+            let Some(buf) = buf.get(..amt) else {
+                tracing::warn!("Error slicing buffer");
+                continue;
+            };
+            c.send(Vec::from(buf)).await.unwrap_or_else(|e| {
+                tracing::warn!("Error sending packet to worker: {}", e);
+            });
+        }
+    }
+}
+
+/// Passes events of `logaddress_add` streams to workers of their servers
+#[allow(clippy::infinite_loop)]
+async fn read_logs(socket: UdpSocket, routes: Routes) {
+    let mut buf = [0; MAX_REPLY_SIZE];
+    loop {
+        let Ok((amt, src)) = socket.recv_from(&mut buf).await else {
+            tracing::warn!("Error reading from log socket");
+            continue;
+        };
+        let channel = routes.read().ok().and_then(|routes| {
+            routes.get(&src).map(|route| route.logs.clone())
+        });
+        let Some(c) = channel else {
+            tracing::trace!("Log from unknown server {}", src);
+            continue;
+        };
+        let Some(line) = buf.get(..amt).and_then(logs::parse_packet) else {
+            tracing::debug!("Invalid log packet from {}", src);
+            continue;
+        };
+        tracing::trace!(server = %src, "{}", line);
+        if let Some(event) = logs::parse_line(&line) {
+            c.send(event).await.unwrap_or_else(|e| {
+                tracing::warn!("Error sending log event to worker: {}", e);
+            });
+        }
+    }
+}
+
+/// Monitors configured servers together with targets of the `file_sd`
+/// file and servers discovered from the master server
+async fn update_servers(
+    servers: &mut Servers,
+    config: &Config,
+    file_targets: &[ServerSpec],
+    discovered: &[ServerSpec],
+) {
+    match config.server_list_with(&[file_targets, discovered].concat()) {
+        Ok(server_list) => servers.update(server_list).await,
+        Err(e) => tracing::warn!("Error updating servers: {:#}", e),
+    }
+}
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> anyhow::Result<()> {
     dotenv().ok();
@@ -55,65 +154,93 @@ async fn main() -> anyhow::Result<()> {
     setup_logger(config.log_level, config.log_format);
 
     config.log();
-    let m = metrics::Metrics::new(config.metrics_addr.to_string());
-    m.listen()?;
+    config.validate()?;
+    let (tx_reload, mut rx_reload) = mpsc::channel::<()>(1);
+    let m = metrics::Metrics::new(
+        config.metrics_addr.to_string(),
+        &config.query_duration_buckets,
+    );
+    let prober = Arc::new(Prober::new(
+        config.probe_options(&ModuleSpec::default()),
+        config.query_duration_buckets.clone(),
+    ));
+    prober.set_modules(config.probe_modules()?);
+    let history = History::open(&config)?.map(Arc::new);
+    let events = Arc::new(Events::new());
+    m.listen(
+        tx_reload.clone(),
+        Arc::clone(&prober),
+        history.clone(),
+        Arc::clone(&events),
+    )?;
+    #[cfg(unix)]
+    forward_hangup(tx_reload)?;
 
     let socket = Arc::new(UdpSocket::bind(config.listen_addr).await?);
 
-    let mut servers = vec![];
-    let mut addr_to_channel = HashMap::new();
     let shared_metrics = Arc::new(m);
-    for addr in config.server_addr {
-        if addr_to_channel.contains_key(&addr) {
-            tracing::warn!("Duplicate server address: {}. Skipping", addr);
-            continue;
-        }
-        let (tx_challenge, rx_challenge) = mpsc::channel::<Vec<u8>>(1);
-        let (tx_packet, rx_packet) = mpsc::channel::<Vec<u8>>(1);
-        let mut interval = time::interval(Duration::from_secs(5));
-        interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
-        let gs = hlds::GameServer::new(
-            addr,
-            interval,
-            rx_challenge,
-            tx_challenge,
-            rx_packet,
-            Arc::clone(&socket),
-            Arc::clone(&shared_metrics),
-        );
-        servers.push(gs);
-        addr_to_channel.insert(addr, tx_packet);
+    let mut servers = Servers::new(
+        Arc::clone(&socket),
+        Arc::clone(&shared_metrics),
+        history.as_ref().map(History::start),
+        events.sender(),
+    );
+    servers.update(config.server_list()?).await;
+    if let Some(webhooks) = Webhooks::start(config.webhooks()?) {
+        tokio::spawn(webhooks.run(events.sender().subscribe()));
     }
 
-    for server in servers {
-        let mut server = server;
-        tokio::spawn(async move {
-            server.process().await;
-        });
+    let mut discovered: Vec<ServerSpec> = vec![];
+    let (tx_discovered, mut rx_discovered) = mpsc::channel(1);
+    if let Some(discovery) = Discovery::new(&config) {
+        tokio::spawn(discovery.run(config.master_interval, tx_discovered));
+    }
+    let mut file_targets: Vec<ServerSpec> = vec![];
+    let (tx_file_targets, mut rx_file_targets) = mpsc::channel(1);
+    if let Some(path) = &config.file_sd_path {
+        let file_sd = FileSd::new(path.clone(), config.file_sd_interval);
+        tokio::spawn(file_sd.run(tx_file_targets));
     }
 
-    #[allow(clippy::infinite_loop)]
-    let reader = tokio::spawn(async move {
-        let mut buf = [0; MAX_REPLY_SIZE];
-        loop {
-            let Ok((amt, src)) = socket.recv_from(&mut buf).await else {
-                tracing::warn!("Error reading from socket");
-                continue;
-            };
-            let channel = addr_to_channel.get(&src);
-            if let Some(c) = channel {
-                let Some(buf) = buf.get(..amt) else {
-                    tracing::warn!("Error slicing buffer");
-                    continue;
-                };
-                c.send(Vec::from(buf)).await.unwrap_or_else(|e| {
-                    tracing::warn!("Error sending packet to worker: {}", e);
-                });
+    let mut reader = tokio::spawn(read_replies(socket, servers.routes()));
+
+    if let Some(log_listen_addr) = config.log_listen_addr {
+        let log_socket = UdpSocket::bind(log_listen_addr).await?;
+        tokio::spawn(read_logs(log_socket, servers.routes()));
+    }
+
+    let mut dns_interval = time::interval(config.dns_ttl);
+    dns_interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+    // First tick completes immediately and hostnames were just resolved
+    dns_interval.tick().await;
+
+    loop {
+        select! {
+            result = &mut reader => {
+                result?;
+                break;
+            }
+            _ = dns_interval.tick() => {
+                servers.resolve().await;
+            }
+            Some(addrs) = rx_discovered.recv() => {
+                discovered = addrs.into_iter().map(ServerSpec::from).collect();
+                update_servers(&mut servers, &config, &file_targets, &discovered).await;
+            }
+            Some(targets) = rx_file_targets.recv() => {
+                file_targets = targets;
+                update_servers(&mut servers, &config, &file_targets, &discovered).await;
+            }
+            Some(()) = rx_reload.recv() => {
+                tracing::info!("Reloading configuration");
+                update_servers(&mut servers, &config, &file_targets, &discovered).await;
+                match config.probe_modules() {
+                    Ok(modules) => prober.set_modules(modules),
+                    Err(e) => tracing::warn!("Error reloading probe modules: {:#}", e),
+                }
             }
         }
-    });
-
-    reader.await?;
+    }
 
     Ok(())
 }
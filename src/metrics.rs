@@ -1,61 +1,751 @@
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, AtomicU64};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use anyhow::bail;
-use prometheus_client::metrics::family::Family;
+use anyhow::{anyhow, bail};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::{Family, MetricConstructor};
+use prometheus_client::metrics::histogram::Histogram;
 use prometheus_client::registry::Registry;
 use prometheus_client::{encoding::text::encode, metrics::gauge::Gauge};
-use tiny_http::{Response, Server};
+use tiny_http::{Method, Response, Server};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::Sender;
+
+use crate::api::{Api, InfoStatus};
+use crate::config::ServerOptions;
+use crate::cstrike;
+use crate::events::Events;
+use crate::history::History;
+use crate::hlds::{PlayerInfo, Query};
+use crate::probe::Prober;
+use crate::rcon::{PlayerStatus, Stats};
+
+/// Buckets of the player session duration histogram in seconds
+const SESSION_DURATION_BUCKETS: [f64; 8] =
+    [60.0, 300.0, 600.0, 1800.0, 3600.0, 7200.0, 14400.0, 28800.0];
+
+/// Info style family which keeps a single series per server, replacing it
+/// when labels change
+#[derive(Default)]
+struct InfoFamily {
+    family: Family<Vec<(String, String)>, Gauge>,
+    current: Mutex<HashMap<SocketAddr, Vec<(String, String)>>>,
+}
+
+impl InfoFamily {
+    /// Sets the series of a server and returns the replaced labels
+    fn set(
+        &self,
+        addr: SocketAddr,
+        labels: Vec<(String, String)>,
+    ) -> Option<Vec<(String, String)>> {
+        self.family.get_or_create(&labels).set(1);
+        let Ok(mut current) = self.current.lock() else {
+            tracing::debug!("Can't access info labels");
+            return None;
+        };
+        if current.get(&addr) == Some(&labels) {
+            return None;
+        }
+        let previous = current.insert(addr, labels)?;
+        self.family.remove(&previous);
+        Some(previous)
+    }
+
+    fn remove(&self, addr: SocketAddr) {
+        let Ok(mut current) = self.current.lock() else {
+            tracing::debug!("Can't access info labels");
+            return;
+        };
+        if let Some(labels) = current.remove(&addr) {
+            self.family.remove(&labels);
+        }
+    }
+}
+
+/// Label sets of every series created for each server
+type ServerSeries = HashMap<SocketAddr, HashSet<Vec<(String, String)>>>;
+
+/// Family from which series of a removed server can be deleted
+trait Series {
+    fn remove_series(&self, series: &HashSet<Vec<(String, String)>>);
+}
+
+impl<M, C: MetricConstructor<M>> Series
+    for Family<Vec<(String, String)>, M, C>
+{
+    fn remove_series(&self, series: &HashSet<Vec<(String, String)>>) {
+        for labels in series {
+            self.remove(labels);
+        }
+    }
+}
+
+/// Labels configured for a server
+#[derive(Clone)]
+struct ServerLabels {
+    addr: String,
+    labels: Vec<(String, String)>,
+}
+
+/// Constructs histograms with configured buckets
+#[derive(Clone)]
+struct Buckets(Vec<f64>);
+
+impl MetricConstructor<Histogram> for Buckets {
+    fn new_metric(&self) -> Histogram {
+        Histogram::new(self.0.iter().copied())
+    }
+}
 
 pub struct Metrics {
     registry: Arc<Mutex<Registry>>,
     export_addr: String,
     players: Family<Vec<(String, String)>, Gauge>,
     bots: Family<Vec<(String, String)>, Gauge>,
-    info: Family<Vec<(String, String)>, Gauge>,
+    max_players: Family<Vec<(String, String)>, Gauge>,
+    info: InfoFamily,
     up: Family<Vec<(String, String)>, Gauge>,
+    player_score: Family<Vec<(String, String)>, Gauge>,
+    player_connected: Family<Vec<(String, String)>, Gauge<f64, AtomicU64>>,
+    session_duration: Family<Vec<(String, String)>, Histogram, Buckets>,
+    sessions: Family<Vec<(String, String)>, Counter>,
+    unique_players: Family<Vec<(String, String)>, Gauge>,
+    player_names: Mutex<HashMap<SocketAddr, HashSet<String>>>,
+    server_labels: Mutex<HashMap<SocketAddr, ServerLabels>>,
+    series: Mutex<ServerSeries>,
+    rule: Family<Vec<(String, String)>, Gauge<f64, AtomicU64>>,
+    rules_info: InfoFamily,
+    split_failures: Family<Vec<(String, String)>, Counter>,
+    mod_info: InfoFamily,
+    extra_info: InfoFamily,
+    map_info: InfoFamily,
+    details_info: InfoFamily,
+    vac_secured: Family<Vec<(String, String)>, Gauge>,
+    password_protected: Family<Vec<(String, String)>, Gauge>,
+    map_changes: Family<Vec<(String, String)>, Counter>,
+    map_start: Family<Vec<(String, String)>, Gauge<f64, AtomicU64>>,
+    map_player_seconds: Family<Vec<(String, String)>, Counter<f64, AtomicU64>>,
+    query_duration: Family<Vec<(String, String)>, Histogram, Buckets>,
+    queries_sent: Family<Vec<(String, String)>, Counter>,
+    replies_received: Family<Vec<(String, String)>, Counter>,
+    query_timeouts: Family<Vec<(String, String)>, Counter>,
+    parse_errors: Family<Vec<(String, String)>, Counter>,
+    challenges_received: Family<Vec<(String, String)>, Counter>,
+    cpu: Family<Vec<(String, String)>, Gauge<f64, AtomicU64>>,
+    fps: Family<Vec<(String, String)>, Gauge<f64, AtomicU64>>,
+    net_in: Family<Vec<(String, String)>, Gauge<f64, AtomicU64>>,
+    net_out: Family<Vec<(String, String)>, Gauge<f64, AtomicU64>>,
+    uptime: Family<Vec<(String, String)>, Gauge<f64, AtomicU64>>,
+    player_ping: Family<Vec<(String, String)>, Gauge>,
+    ping_names: Mutex<HashMap<SocketAddr, HashSet<String>>>,
+    log_events: Family<Vec<(String, String)>, Counter>,
+    kills: Family<Vec<(String, String)>, Counter>,
+    kills_by_weapon: Family<Vec<(String, String)>, Counter>,
+    headshots: Family<Vec<(String, String)>, Counter>,
+    suicides: Family<Vec<(String, String)>, Counter>,
+    connects: Family<Vec<(String, String)>, Counter>,
+    disconnects: Family<Vec<(String, String)>, Counter>,
+    rounds: Family<Vec<(String, String)>, Counter>,
+    team_score: Family<Vec<(String, String)>, Gauge>,
+    round_wins: Family<Vec<(String, String)>, Counter>,
+    bomb_events: Family<Vec<(String, String)>, Counter>,
+    hostage_events: Family<Vec<(String, String)>, Counter>,
+    purchases: Family<Vec<(String, String)>, Counter>,
+    api: Arc<Api>,
 }
 
 impl Metrics {
-    pub fn new(metrics_addr: String) -> Self {
+    pub fn new(metrics_addr: String, buckets: &[f64]) -> Self {
+        let mut buckets = buckets.to_vec();
+        buckets.sort_by(f64::total_cmp);
+        buckets.dedup();
         let metrics = Self {
             registry: Arc::new(Mutex::new(Registry::default())),
             export_addr: metrics_addr,
             players: Family::default(),
             bots: Family::default(),
-            info: Family::default(),
+            max_players: Family::default(),
+            info: InfoFamily::default(),
             up: Family::default(),
+            player_score: Family::default(),
+            player_connected: Family::default(),
+            session_duration: Family::new_with_constructor(Buckets(
+                SESSION_DURATION_BUCKETS.to_vec(),
+            )),
+            sessions: Family::default(),
+            unique_players: Family::default(),
+            player_names: Mutex::new(HashMap::new()),
+            server_labels: Mutex::new(HashMap::new()),
+            series: Mutex::new(HashMap::new()),
+            rule: Family::default(),
+            rules_info: InfoFamily::default(),
+            split_failures: Family::default(),
+            mod_info: InfoFamily::default(),
+            extra_info: InfoFamily::default(),
+            map_info: InfoFamily::default(),
+            details_info: InfoFamily::default(),
+            vac_secured: Family::default(),
+            password_protected: Family::default(),
+            map_changes: Family::default(),
+            map_start: Family::default(),
+            map_player_seconds: Family::default(),
+            query_duration: Family::new_with_constructor(Buckets(buckets)),
+            queries_sent: Family::default(),
+            replies_received: Family::default(),
+            query_timeouts: Family::default(),
+            parse_errors: Family::default(),
+            challenges_received: Family::default(),
+            cpu: Family::default(),
+            fps: Family::default(),
+            net_in: Family::default(),
+            net_out: Family::default(),
+            uptime: Family::default(),
+            player_ping: Family::default(),
+            ping_names: Mutex::new(HashMap::new()),
+            log_events: Family::default(),
+            kills: Family::default(),
+            kills_by_weapon: Family::default(),
+            headshots: Family::default(),
+            suicides: Family::default(),
+            connects: Family::default(),
+            disconnects: Family::default(),
+            rounds: Family::default(),
+            team_score: Family::default(),
+            round_wins: Family::default(),
+            bomb_events: Family::default(),
+            hostage_events: Family::default(),
+            purchases: Family::default(),
+            api: Arc::new(Api::default()),
         };
         let mut m = metrics.registry.lock().unwrap();
-        m.register("hlds_info", "server info", metrics.info.clone());
+        metrics.register_server(&mut m);
+        metrics.register_players(&mut m);
+        metrics.register_maps(&mut m);
+        metrics.register_queries(&mut m);
+        metrics.register_rcon(&mut m);
+        metrics.register_logs(&mut m);
+        metrics.register_cstrike(&mut m);
+        drop(m);
+        metrics
+    }
+
+    fn register_server(&self, m: &mut Registry) {
+        m.register("hlds_info", "server info", self.info.family.clone());
+        m.register("hlds_up", "server is up", self.up.clone());
+        m.register(
+            "hlds_rule",
+            "numeric value of a server rule",
+            self.rule.clone(),
+        );
+        m.register(
+            "hlds_rules_info",
+            "server rules",
+            self.rules_info.family.clone(),
+        );
+        m.register(
+            "hlds_split_reassembly_failures",
+            "split packets which could not be reassembled",
+            self.split_failures.clone(),
+        );
+
+        m.register(
+            "hlds_mod_info",
+            "mod info of GoldSrc servers",
+            self.mod_info.family.clone(),
+        );
+        m.register(
+            "hlds_extra_info",
+            "extra server info",
+            self.extra_info.family.clone(),
+        );
+        m.register(
+            "hlds_server_details_info",
+            "server details",
+            self.details_info.family.clone(),
+        );
+        m.register(
+            "hlds_vac_secured",
+            "server is secured by VAC",
+            self.vac_secured.clone(),
+        );
+        m.register(
+            "hlds_password_protected",
+            "server requires a password",
+            self.password_protected.clone(),
+        );
+    }
+
+    fn register_queries(&self, m: &mut Registry) {
+        m.register(
+            "hlds_query_duration_seconds",
+            "round-trip time of server queries",
+            self.query_duration.clone(),
+        );
+        m.register(
+            "hlds_queries_sent",
+            "number of queries sent",
+            self.queries_sent.clone(),
+        );
+        m.register(
+            "hlds_replies_received",
+            "number of packets received",
+            self.replies_received.clone(),
+        );
+        m.register(
+            "hlds_query_timeouts",
+            "number of queries left unanswered",
+            self.query_timeouts.clone(),
+        );
+        m.register(
+            "hlds_parse_errors",
+            "number of packets which could not be parsed",
+            self.parse_errors.clone(),
+        );
+        m.register(
+            "hlds_challenges_received",
+            "number of challenges received",
+            self.challenges_received.clone(),
+        );
+    }
+
+    fn register_rcon(&self, m: &mut Registry) {
+        m.register("hlds_cpu_percent", "server CPU usage", self.cpu.clone());
+        m.register("hlds_fps", "server frames per second", self.fps.clone());
+        m.register(
+            "hlds_net_in_kbps",
+            "incoming traffic in KB/s",
+            self.net_in.clone(),
+        );
+        m.register(
+            "hlds_net_out_kbps",
+            "outgoing traffic in KB/s",
+            self.net_out.clone(),
+        );
+        m.register(
+            "hlds_uptime_minutes",
+            "server uptime",
+            self.uptime.clone(),
+        );
+        m.register(
+            "hlds_player_ping_milliseconds",
+            "current ping of a player",
+            self.player_ping.clone(),
+        );
+    }
+
+    fn register_logs(&self, m: &mut Registry) {
+        m.register(
+            "hlds_log_events",
+            "number of events received in server logs",
+            self.log_events.clone(),
+        );
+        m.register("hlds_kills", "number of kills", self.kills.clone());
+        m.register(
+            "hlds_kills_by_weapon",
+            "number of kills by weapon",
+            self.kills_by_weapon.clone(),
+        );
+        m.register(
+            "hlds_headshots",
+            "number of headshot kills by weapon",
+            self.headshots.clone(),
+        );
+        m.register(
+            "hlds_suicides",
+            "number of suicides",
+            self.suicides.clone(),
+        );
+        m.register(
+            "hlds_connects",
+            "number of player connections",
+            self.connects.clone(),
+        );
+        m.register(
+            "hlds_disconnects",
+            "number of player disconnections",
+            self.disconnects.clone(),
+        );
+        m.register("hlds_rounds", "number of rounds", self.rounds.clone());
+    }
+
+    fn register_cstrike(&self, m: &mut Registry) {
+        m.register(
+            "hlds_cstrike_team_score",
+            "current score of a team",
+            self.team_score.clone(),
+        );
+        m.register(
+            "hlds_cstrike_round_wins",
+            "number of rounds won by team and reason",
+            self.round_wins.clone(),
+        );
+        m.register(
+            "hlds_cstrike_bomb_events",
+            "number of bomb events",
+            self.bomb_events.clone(),
+        );
+        m.register(
+            "hlds_cstrike_hostage_events",
+            "number of hostage events",
+            self.hostage_events.clone(),
+        );
+        m.register(
+            "hlds_cstrike_purchases",
+            "number of purchased items",
+            self.purchases.clone(),
+        );
+    }
+
+    fn register_players(&self, m: &mut Registry) {
         m.register(
             "hlds_players",
             "current number of players",
-            metrics.players.clone(),
+            self.players.clone(),
         );
+        m.register("hlds_bots", "current number of bots", self.bots.clone());
         m.register(
-            "hlds_bots",
-            "current number of bots",
-            metrics.bots.clone(),
+            "hlds_max_players",
+            "maximum number of players",
+            self.max_players.clone(),
         );
         m.register(
-            "hlds_up",
-            "server is up",
-            metrics.up.clone(),
+            "hlds_player_score",
+            "current score of a player",
+            self.player_score.clone(),
+        );
+        m.register(
+            "hlds_player_connected_seconds",
+            "time a player has been connected",
+            self.player_connected.clone(),
+        );
+        m.register(
+            "hlds_player_session_duration_seconds",
+            "duration of ended player sessions",
+            self.session_duration.clone(),
+        );
+        m.register(
+            "hlds_player_sessions",
+            "number of started player sessions",
+            self.sessions.clone(),
+        );
+        m.register(
+            "hlds_unique_players",
+            "number of distinct players seen within a window",
+            self.unique_players.clone(),
+        );
+    }
+
+    fn register_maps(&self, m: &mut Registry) {
+        m.register(
+            "hlds_map_info",
+            "current map",
+            self.map_info.family.clone(),
+        );
+        m.register(
+            "hlds_map_changes",
+            "number of map changes",
+            self.map_changes.clone(),
+        );
+        m.register(
+            "hlds_current_map_start_timestamp_seconds",
+            "time when the current map was first observed",
+            self.map_start.clone(),
+        );
+        m.register(
+            "hlds_map_player_seconds",
+            "accumulated time players spent on a map",
+            self.map_player_seconds.clone(),
         );
-        drop(m);
-        metrics
     }
 
-    pub fn observe_players(&self, addr: SocketAddr, players: u8, bots: u8) {
+    /// Sets labels which are added to every series of a server
+    pub fn set_server_labels(
+        &self,
+        addr: SocketAddr,
+        options: &ServerOptions,
+    ) {
+        let mut labels: Vec<_> = options
+            .name
+            .clone()
+            .map(|name| ("server".to_string(), name))
+            .into_iter()
+            .collect();
+        labels.extend(options.labels.clone());
+        let Ok(mut servers) = self.server_labels.lock() else {
+            tracing::debug!("Can't access server labels");
+            return;
+        };
+        // Hostname keeps series stable when its address changes
+        let label = options.host.clone().unwrap_or_else(|| addr.to_string());
+        servers.insert(
+            addr,
+            ServerLabels {
+                addr: label.clone(),
+                labels,
+            },
+        );
+        drop(servers);
+        self.api.update(addr, |status| status.addr = label);
+    }
+
+    /// Labels of a series with server address and configured server labels
+    fn labels(
+        &self,
+        addr: SocketAddr,
+        labels: Vec<(String, String)>,
+    ) -> Vec<(String, String)> {
+        let server = self
+            .server_labels
+            .lock()
+            .ok()
+            .and_then(|servers| servers.get(&addr).cloned())
+            .unwrap_or_else(|| ServerLabels {
+                addr: addr.to_string(),
+                labels: vec![],
+            });
+        let mut result = vec![("addr".to_string(), server.addr)];
+        result.extend(labels);
+        // Labels set by the exporter take precedence over configured ones
+        for (name, value) in server.labels {
+            if !result.iter().any(|(n, _)| *n == name) {
+                result.push((name, value));
+            }
+        }
+        if let Ok(mut series) = self.series.lock() {
+            series.entry(addr).or_default().insert(result.clone());
+        }
+        result
+    }
+
+    /// Moves state of a server which hostname resolved to a new address
+    pub fn rename_server(&self, old: SocketAddr, new: SocketAddr) {
+        fn rename<T>(
+            map: &Mutex<HashMap<SocketAddr, T>>,
+            old: SocketAddr,
+            new: SocketAddr,
+        ) {
+            if let Ok(mut map) = map.lock() {
+                if let Some(value) = map.remove(&old) {
+                    map.insert(new, value);
+                }
+            }
+        }
+
+        rename(&self.series, old, new);
+        rename(&self.server_labels, old, new);
+        rename(&self.player_names, old, new);
+        rename(&self.ping_names, old, new);
+        self.api.rename(old, new);
+        for info in [
+            &self.info,
+            &self.rules_info,
+            &self.mod_info,
+            &self.extra_info,
+            &self.map_info,
+            &self.details_info,
+        ] {
+            rename(&info.current, old, new);
+        }
+    }
+
+    fn set_info(
+        &self,
+        info: &InfoFamily,
+        addr: SocketAddr,
+        labels: Vec<(String, String)>,
+    ) {
+        if let Some(previous) = info.set(addr, labels) {
+            self.forget(addr, &previous);
+        }
+    }
+
+    /// Stops tracking labels of a series which was removed
+    fn forget(&self, addr: SocketAddr, labels: &Vec<(String, String)>) {
+        if let Ok(mut series) = self.series.lock() {
+            if let Some(series) = series.get_mut(&addr) {
+                series.remove(labels);
+            }
+        }
+    }
+
+    fn families(&self) -> [&dyn Series; 47] {
+        [
+            &self.players,
+            &self.bots,
+            &self.max_players,
+            &self.info.family,
+            &self.up,
+            &self.player_score,
+            &self.player_connected,
+            &self.session_duration,
+            &self.sessions,
+            &self.unique_players,
+            &self.rule,
+            &self.rules_info.family,
+            &self.split_failures,
+            &self.mod_info.family,
+            &self.extra_info.family,
+            &self.map_info.family,
+            &self.details_info.family,
+            &self.vac_secured,
+            &self.password_protected,
+            &self.map_changes,
+            &self.map_start,
+            &self.map_player_seconds,
+            &self.query_duration,
+            &self.queries_sent,
+            &self.replies_received,
+            &self.query_timeouts,
+            &self.parse_errors,
+            &self.challenges_received,
+            &self.cpu,
+            &self.fps,
+            &self.net_in,
+            &self.net_out,
+            &self.uptime,
+            &self.player_ping,
+            &self.log_events,
+            &self.kills,
+            &self.kills_by_weapon,
+            &self.headshots,
+            &self.suicides,
+            &self.connects,
+            &self.disconnects,
+            &self.rounds,
+            &self.team_score,
+            &self.round_wins,
+            &self.bomb_events,
+            &self.hostage_events,
+            &self.purchases,
+        ]
+    }
+
+    /// Deletes every series of a server which is no longer monitored
+    pub fn remove_server(&self, addr: SocketAddr) {
+        let series = self
+            .series
+            .lock()
+            .ok()
+            .and_then(|mut series| series.remove(&addr))
+            .unwrap_or_default();
+        for family in self.families() {
+            family.remove_series(&series);
+        }
+        for info in [
+            &self.info,
+            &self.rules_info,
+            &self.mod_info,
+            &self.extra_info,
+            &self.map_info,
+            &self.details_info,
+        ] {
+            info.remove(addr);
+        }
+        for names in [&self.player_names, &self.ping_names] {
+            if let Ok(mut names) = names.lock() {
+                names.remove(&addr);
+            }
+        }
+        if let Ok(mut server_labels) = self.server_labels.lock() {
+            server_labels.remove(&addr);
+        }
+        self.api.remove(addr);
+    }
+
+    pub fn observe_players(
+        &self,
+        addr: SocketAddr,
+        players: u8,
+        max_players: u8,
+        bots: u8,
+    ) {
         self.players
-            .get_or_create(&vec![("addr".to_string(), addr.to_string())])
+            .get_or_create(&self.labels(addr, vec![]))
             .set(i64::from(players));
+        self.max_players
+            .get_or_create(&self.labels(addr, vec![]))
+            .set(i64::from(max_players));
         self.bots
-            .get_or_create(&vec![("addr".to_string(), addr.to_string())])
+            .get_or_create(&self.labels(addr, vec![]))
             .set(i64::from(bots));
     }
 
+    pub fn observe_map(&self, addr: SocketAddr, map: String) {
+        self.set_info(
+            &self.map_info,
+            addr,
+            self.labels(addr, vec![("map".to_string(), map)]),
+        );
+    }
+
+    pub fn observe_map_change(&self, addr: SocketAddr) {
+        self.map_changes
+            .get_or_create(&self.labels(addr, vec![]))
+            .inc();
+        self.observe_map_start(addr);
+    }
+
+    pub fn observe_map_start(&self, addr: SocketAddr) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.map_start
+            .get_or_create(&self.labels(addr, vec![]))
+            .set(now.as_secs_f64());
+    }
+
+    pub fn observe_map_player_seconds(
+        &self,
+        addr: SocketAddr,
+        map: String,
+        seconds: f64,
+    ) {
+        self.map_player_seconds
+            .get_or_create(&self.labels(addr, vec![("map".to_string(), map)]))
+            .inc_by(seconds);
+    }
+
+    pub fn observe_security(
+        &self,
+        addr: SocketAddr,
+        vac: bool,
+        private: bool,
+    ) {
+        self.vac_secured
+            .get_or_create(&self.labels(addr, vec![]))
+            .set(vac.into());
+        self.password_protected
+            .get_or_create(&self.labels(addr, vec![]))
+            .set(private.into());
+    }
+
+    pub fn observe_details(
+        &self,
+        addr: SocketAddr,
+        folder: String,
+        protocol: u8,
+        os: &str,
+        server_type: &str,
+    ) {
+        self.set_info(
+            &self.details_info,
+            addr,
+            self.labels(
+                addr,
+                vec![
+                    ("folder".to_string(), folder),
+                    ("protocol".to_string(), protocol.to_string()),
+                    ("os".to_string(), os.to_string()),
+                    ("server_type".to_string(), server_type.to_string()),
+                ],
+            ),
+        );
+    }
+
     pub fn observe_info(
         &self,
         addr: SocketAddr,
@@ -63,46 +753,553 @@ impl Metrics {
         game: String,
         version: String,
     ) {
-        self.info
-            .get_or_create(&vec![
-                ("name".to_string(), name),
-                ("addr".to_string(), addr.to_string()),
-                ("game".to_string(), game),
-                ("version".to_string(), version),
-            ])
-            .set(1);
+        self.set_info(
+            &self.info,
+            addr,
+            self.labels(
+                addr,
+                vec![
+                    ("name".to_string(), name),
+                    ("game".to_string(), game),
+                    ("version".to_string(), version),
+                ],
+            ),
+        );
+    }
+
+    pub fn observe_extra_info(
+        &self,
+        addr: SocketAddr,
+        port: Option<u16>,
+        steam_id: Option<u64>,
+        keywords: Option<String>,
+        game_id: Option<u64>,
+    ) {
+        let to_label = |value: Option<String>| value.unwrap_or_default();
+        self.set_info(
+            &self.extra_info,
+            addr,
+            self.labels(
+                addr,
+                vec![
+                    (
+                        "port".to_string(),
+                        to_label(port.map(|v| v.to_string())),
+                    ),
+                    (
+                        "steam_id".to_string(),
+                        to_label(steam_id.map(|v| v.to_string())),
+                    ),
+                    ("keywords".to_string(), to_label(keywords)),
+                    (
+                        "game_id".to_string(),
+                        to_label(game_id.map(|v| v.to_string())),
+                    ),
+                ],
+            ),
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn observe_mod_info(
+        &self,
+        addr: SocketAddr,
+        link: String,
+        download_link: String,
+        version: i32,
+        size: i32,
+        type_: &str,
+        dll: &str,
+    ) {
+        self.set_info(
+            &self.mod_info,
+            addr,
+            self.labels(
+                addr,
+                vec![
+                    ("link".to_string(), link),
+                    ("download_link".to_string(), download_link),
+                    ("version".to_string(), version.to_string()),
+                    ("size".to_string(), size.to_string()),
+                    ("type".to_string(), type_.to_string()),
+                    ("dll".to_string(), dll.to_string()),
+                ],
+            ),
+        );
     }
 
     pub fn observe_up(&self, addr: SocketAddr, up: bool) {
         self.up
-            .get_or_create(&vec![("addr".to_string(), addr.to_string())])
+            .get_or_create(&self.labels(addr, vec![]))
             .set(up.into());
+        self.api.update(addr, |status| status.up = up);
+    }
+
+    /// Keeps the latest info reply for the status API
+    pub fn observe_status(&self, addr: SocketAddr, info: InfoStatus) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.api.update(addr, |status| {
+            status.info = Some(info);
+            status.last_update = Some(now.as_secs());
+        });
+    }
+
+    pub fn observe_player_list(
+        &self,
+        addr: SocketAddr,
+        players: &[PlayerInfo],
+    ) {
+        self.api
+            .update(addr, |status| status.players = Some(players.to_vec()));
+        let mut names = HashSet::new();
+        // Players which are still connecting have no name yet
+        for player in players.iter().filter(|p| !p.name.is_empty()) {
+            let labels = self
+                .labels(addr, vec![("name".to_string(), player.name.clone())]);
+            self.player_score
+                .get_or_create(&labels)
+                .set(i64::from(player.score));
+            self.player_connected
+                .get_or_create(&labels)
+                .set(f64::from(player.duration));
+            names.insert(player.name.clone());
+        }
+
+        let Ok(mut player_names) = self.player_names.lock() else {
+            tracing::debug!("Can't access player names");
+            return;
+        };
+        let previous =
+            player_names.insert(addr, names.clone()).unwrap_or_default();
+        drop(player_names);
+        for name in previous.difference(&names) {
+            let labels =
+                self.labels(addr, vec![("name".to_string(), name.clone())]);
+            self.player_score.remove(&labels);
+            self.player_connected.remove(&labels);
+            self.forget(addr, &labels);
+        }
+    }
+
+    pub fn observe_sessions(
+        &self,
+        addr: SocketAddr,
+        started: usize,
+        ended: &[Duration],
+    ) {
+        let labels = self.labels(addr, vec![]);
+        self.sessions.get_or_create(&labels).inc_by(started as u64);
+        let histogram = self.session_duration.get_or_create(&labels);
+        for duration in ended {
+            histogram.observe(duration.as_secs_f64());
+        }
+    }
+
+    pub fn observe_unique_players(
+        &self,
+        addr: SocketAddr,
+        unique: &[(Duration, usize)],
+    ) {
+        for (window, count) in unique {
+            self.unique_players
+                .get_or_create(&self.labels(
+                    addr,
+                    vec![("window".to_string(), window_label(*window))],
+                ))
+                .set(i64::try_from(*count).unwrap_or(i64::MAX));
+        }
+    }
+
+    pub fn observe_stats(&self, addr: SocketAddr, stats: &Stats) {
+        let labels = self.labels(addr, vec![]);
+        self.cpu.get_or_create(&labels).set(stats.cpu);
+        self.fps.get_or_create(&labels).set(stats.fps);
+        self.net_in.get_or_create(&labels).set(stats.net_in);
+        self.net_out.get_or_create(&labels).set(stats.net_out);
+        self.uptime.get_or_create(&labels).set(stats.uptime);
+    }
+
+    pub fn observe_player_pings(
+        &self,
+        addr: SocketAddr,
+        players: &[PlayerStatus],
+    ) {
+        let mut names = HashSet::new();
+        for player in players {
+            let labels = self
+                .labels(addr, vec![("name".to_string(), player.name.clone())]);
+            self.player_ping
+                .get_or_create(&labels)
+                .set(i64::from(player.ping));
+            names.insert(player.name.clone());
+        }
+
+        let Ok(mut ping_names) = self.ping_names.lock() else {
+            tracing::debug!("Can't access player names");
+            return;
+        };
+        let previous =
+            ping_names.insert(addr, names.clone()).unwrap_or_default();
+        drop(ping_names);
+        for name in previous.difference(&names) {
+            let labels =
+                self.labels(addr, vec![("name".to_string(), name.clone())]);
+            self.player_ping.remove(&labels);
+            self.forget(addr, &labels);
+        }
+    }
+
+    pub fn observe_log_event(&self, addr: SocketAddr, event: &str) {
+        self.log_events
+            .get_or_create(
+                &self.labels(addr, vec![("event".to_string(), event.into())]),
+            )
+            .inc();
+    }
+
+    pub fn observe_kill(
+        &self,
+        addr: SocketAddr,
+        weapon: &str,
+        headshot: bool,
+    ) {
+        let labels = self
+            .labels(addr, vec![("weapon".to_string(), weapon.to_string())]);
+        self.kills.get_or_create(&labels).inc();
+        self.kills_by_weapon.get_or_create(&labels).inc();
+        if headshot {
+            self.headshots.get_or_create(&labels).inc();
+        }
+    }
+
+    pub fn observe_suicide(&self, addr: SocketAddr, weapon: &str) {
+        self.suicides
+            .get_or_create(&self.labels(
+                addr,
+                vec![("weapon".to_string(), weapon.to_string())],
+            ))
+            .inc();
     }
 
-    pub fn listen(&self) -> anyhow::Result<()> {
+    pub fn observe_connect(&self, addr: SocketAddr) {
+        self.connects
+            .get_or_create(&self.labels(addr, vec![]))
+            .inc();
+    }
+
+    pub fn observe_disconnect(&self, addr: SocketAddr) {
+        self.disconnects
+            .get_or_create(&self.labels(addr, vec![]))
+            .inc();
+    }
+
+    pub fn observe_round_end(&self, addr: SocketAddr, winner: Option<String>) {
+        let winner = winner.unwrap_or_else(|| "none".to_string());
+        self.rounds
+            .get_or_create(
+                &self.labels(addr, vec![("winner".to_string(), winner)]),
+            )
+            .inc();
+    }
+
+    pub fn observe_cstrike_event(
+        &self,
+        addr: SocketAddr,
+        map: &str,
+        event: &cstrike::Event,
+    ) {
+        let map = ("map".to_string(), map.to_string());
+        match event {
+            cstrike::Event::RoundWin { team, reason } => {
+                let labels = vec![
+                    map,
+                    ("team".to_string(), (*team).to_string()),
+                    ("reason".to_string(), reason.clone()),
+                ];
+                self.round_wins
+                    .get_or_create(&self.labels(addr, labels))
+                    .inc();
+            },
+            cstrike::Event::TeamScore { team, score } => {
+                let labels =
+                    vec![map, ("team".to_string(), (*team).to_string())];
+                self.team_score
+                    .get_or_create(&self.labels(addr, labels))
+                    .set(*score);
+            },
+            cstrike::Event::Bomb(event) => {
+                let labels = vec![map, ("event".to_string(), (*event).into())];
+                self.bomb_events
+                    .get_or_create(&self.labels(addr, labels))
+                    .inc();
+            },
+            cstrike::Event::Hostage(event) => {
+                let labels = vec![map, ("event".to_string(), (*event).into())];
+                self.hostage_events
+                    .get_or_create(&self.labels(addr, labels))
+                    .inc();
+            },
+            cstrike::Event::Purchase(item) => {
+                let labels =
+                    vec![map, ("item".to_string(), (*item).to_string())];
+                self.purchases
+                    .get_or_create(&self.labels(addr, labels))
+                    .inc();
+            },
+        }
+    }
+
+    /// Removes team scores and purchases of a map which is no longer played
+    pub fn remove_map(&self, addr: SocketAddr, map: &str) {
+        let map = ("map".to_string(), map.to_string());
+        let Ok(series) = self.series.lock() else {
+            return;
+        };
+        let stale: Vec<_> = series
+            .get(&addr)
+            .map(|series| {
+                series
+                    .iter()
+                    .filter(|labels| labels.contains(&map))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        drop(series);
+        for labels in stale {
+            if self.team_score.remove(&labels) | self.purchases.remove(&labels)
+            {
+                self.forget(addr, &labels);
+            }
+        }
+    }
+
+    pub fn observe_rules(
+        &self,
+        addr: SocketAddr,
+        rules: &[(String, String)],
+        gauges: &[String],
+        labels: &[String],
+    ) {
+        let mut info = vec![];
+        let mut observed = HashSet::new();
+        for (name, value) in rules {
+            if gauges.contains(name) {
+                if let Ok(value) = value.trim().parse::<f64>() {
+                    self.rule
+                        .get_or_create(&self.labels(
+                            addr,
+                            vec![("name".to_string(), name.clone())],
+                        ))
+                        .set(value);
+                    observed.insert(name);
+                }
+            }
+            if labels.contains(name) {
+                info.push((label_name(name), value.clone()));
+            }
+        }
+        // Rules missing from the reply are no longer set on the server
+        for name in gauges.iter().filter(|name| !observed.contains(name)) {
+            let labels =
+                self.labels(addr, vec![("name".to_string(), name.clone())]);
+            if self.rule.remove(&labels) {
+                self.forget(addr, &labels);
+            }
+        }
+        if !labels.is_empty() {
+            self.set_info(&self.rules_info, addr, self.labels(addr, info));
+        }
+    }
+
+    pub fn observe_query_duration(
+        &self,
+        addr: SocketAddr,
+        query: Query,
+        duration: Duration,
+    ) {
+        self.query_duration
+            .get_or_create(&self.labels(
+                addr,
+                vec![("query".to_string(), query.label().to_string())],
+            ))
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn observe_query_sent(&self, addr: SocketAddr, query: Query) {
+        self.queries_sent
+            .get_or_create(&self.labels(
+                addr,
+                vec![("query".to_string(), query.label().to_string())],
+            ))
+            .inc();
+    }
+
+    pub fn observe_query_timeout(&self, addr: SocketAddr, query: Query) {
+        self.query_timeouts
+            .get_or_create(&self.labels(
+                addr,
+                vec![("query".to_string(), query.label().to_string())],
+            ))
+            .inc();
+    }
+
+    pub fn observe_reply(&self, addr: SocketAddr) {
+        self.replies_received
+            .get_or_create(&self.labels(addr, vec![]))
+            .inc();
+    }
+
+    pub fn observe_challenge(&self, addr: SocketAddr) {
+        self.challenges_received
+            .get_or_create(&self.labels(addr, vec![]))
+            .inc();
+    }
+
+    pub fn observe_parse_error(&self, addr: SocketAddr, reason: &str) {
+        self.parse_errors
+            .get_or_create(&self.labels(
+                addr,
+                vec![("reason".to_string(), reason.to_string())],
+            ))
+            .inc();
+    }
+
+    pub fn observe_split_failure(&self, addr: SocketAddr, reason: &str) {
+        self.split_failures
+            .get_or_create(&self.labels(
+                addr,
+                vec![("reason".to_string(), reason.to_string())],
+            ))
+            .inc();
+    }
+
+    /// Encodes metrics of a single probe together with its outcome
+    pub fn encode_probe(
+        &self,
+        success: bool,
+        duration: Duration,
+    ) -> anyhow::Result<String> {
+        let probe_success = Gauge::<i64, AtomicI64>::default();
+        probe_success.set(i64::from(success));
+        let probe_duration = Gauge::<f64, AtomicU64>::default();
+        probe_duration.set(duration.as_secs_f64());
+
+        let mut registry = self
+            .registry
+            .lock()
+            .map_err(|err| anyhow!("Can't access registry {err}"))?;
+        registry.register(
+            "probe_success",
+            "whether the probe was a success",
+            probe_success,
+        );
+        registry.register(
+            "probe_duration_seconds",
+            "how long the probe took to complete",
+            probe_duration,
+        );
+        let mut buf = String::new();
+        encode(&mut buf, &registry)?;
+        drop(registry);
+        Ok(buf)
+    }
+
+    /// Metrics in the text format
+    #[cfg(test)]
+    pub fn encode(&self) -> String {
+        let mut buf = String::new();
+        let registry = self.registry.lock().expect("registry");
+        encode(&mut buf, &registry).expect("metrics should encode");
+        drop(registry);
+        buf
+    }
+
+    pub fn listen(
+        &self,
+        reload: Sender<()>,
+        prober: Arc<Prober>,
+        history: Option<Arc<History>>,
+        events: Arc<Events>,
+    ) -> anyhow::Result<()> {
         let server = match Server::http(&self.export_addr) {
             Ok(server) => server,
             Err(err) => bail!("Can't export metrics: {}", err),
         };
 
         let registry = Arc::clone(&self.registry);
+        let api = Arc::clone(&self.api);
 
-        std::thread::spawn(move || Self::serve_metrics(&server, &registry));
+        std::thread::spawn(move || {
+            Self::serve_metrics(
+                &server,
+                &registry,
+                &reload,
+                &prober,
+                history.as_deref(),
+                &api,
+                &events,
+            );
+        });
         Ok(())
     }
 
-    fn serve_metrics(server: &Server, registry: &Arc<Mutex<Registry>>) {
+    fn serve_metrics(
+        server: &Server,
+        registry: &Arc<Mutex<Registry>>,
+        reload: &Sender<()>,
+        prober: &Arc<Prober>,
+        history: Option<&History>,
+        api: &Api,
+        events: &Events,
+    ) {
         for request in server.incoming_requests() {
-            match request.url() {
+            let url = request.url();
+            let path = url.split_once('?').map_or(url, |(path, _)| path);
+            match path {
                 "/metrics" => Self::export_metrics(request, registry),
-                _ => {
-                    let response = Response::from_string("Not found")
-                        .with_status_code(404);
-                    if let Err(err) = request.respond(response) {
-                        tracing::debug!("Can't send response: {}", err);
-                    }
+                "/-/reload" => Self::reload(request, reload),
+                "/probe" => prober.handle(request),
+                "/history" => match history {
+                    Some(history) => history.handle(request),
+                    None => Self::not_found(request),
+                },
+                "/api/events" => events.handle(request),
+                "/api/servers" => api.handle(request),
+                path if path.starts_with("/api/servers/") => {
+                    api.handle(request);
+                },
+                _ => Self::not_found(request),
+            }
+        }
+    }
+
+    fn not_found(request: tiny_http::Request) {
+        let response =
+            Response::from_string("Not found").with_status_code(404);
+        if let Err(err) = request.respond(response) {
+            tracing::debug!("Can't send response: {}", err);
+        }
+    }
+
+    fn reload(request: tiny_http::Request, reload: &Sender<()>) {
+        let response = if *request.method() == Method::Post {
+            match reload.try_send(()) {
+                // A reload is already pending
+                Ok(()) | Err(TrySendError::Full(())) => {
+                    Response::from_string("Reloading")
+                },
+                Err(TrySendError::Closed(())) => {
+                    Response::from_string("Can't reload").with_status_code(503)
                 },
             }
+        } else {
+            Response::from_string("Method not allowed").with_status_code(405)
+        };
+        if let Err(err) = request.respond(response) {
+            tracing::debug!("Can't send response: {}", err);
         }
     }
 
@@ -128,3 +1325,25 @@ impl Metrics {
         }
     }
 }
+
+/// Label of a window like `1h` or `90s`
+fn window_label(window: Duration) -> String {
+    match window.as_secs() {
+        secs @ 1.. if secs % 3600 == 0 => format!("{}h", secs / 3600),
+        secs @ 1.. if secs % 60 == 0 => format!("{}m", secs / 60),
+        secs => format!("{secs}s"),
+    }
+}
+
+/// Replace characters which are not allowed in prometheus label names
+fn label_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use anyhow::anyhow;
+use tiny_http::{Request, Response};
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{self, Sender};
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::config::ServerOptions;
+use crate::hlds::{GameServer, MAX_REPLY_SIZE};
+use crate::metrics::Metrics;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProbeError {
+    #[error("Target parameter is missing")]
+    MissingTarget,
+    #[error("Unknown module {0}")]
+    UnknownModule(String),
+}
+
+/// Queries targets requested with `/probe?target=host:port&module=name`
+/// and exports their metrics in a registry of their own
+pub struct Prober {
+    runtime: Handle,
+    buckets: Vec<f64>,
+    default: ServerOptions,
+    modules: RwLock<HashMap<String, ServerOptions>>,
+}
+
+impl Prober {
+    /// Creates prober which runs probes on the current tokio runtime
+    pub fn new(default: ServerOptions, buckets: Vec<f64>) -> Self {
+        Self {
+            runtime: Handle::current(),
+            buckets,
+            default,
+            modules: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn set_modules(&self, modules: HashMap<String, ServerOptions>) {
+        match self.modules.write() {
+            Ok(mut current) => *current = modules,
+            Err(err) => tracing::warn!("Can't update probe modules: {}", err),
+        }
+    }
+
+    /// Answers the request once the probe is finished without blocking
+    /// other requests
+    pub fn handle(self: &Arc<Self>, request: Request) {
+        let (target, options) = match self.params(request.url()) {
+            Ok(params) => params,
+            Err(err) => {
+                let response = Response::from_string(err.to_string())
+                    .with_status_code(400);
+                if let Err(err) = request.respond(response) {
+                    tracing::debug!("Can't send response: {}", err);
+                }
+                return;
+            },
+        };
+
+        let prober = Arc::clone(self);
+        self.runtime.spawn(async move {
+            let response = match prober.probe(&target, options).await {
+                Ok(body) => Response::from_string(body),
+                Err(err) => Response::from_string(err.to_string())
+                    .with_status_code(500),
+            };
+            tokio::task::spawn_blocking(move || {
+                if let Err(err) = request.respond(response) {
+                    tracing::debug!("Can't send response: {}", err);
+                }
+            });
+        });
+    }
+
+    fn params(
+        &self,
+        url: &str,
+    ) -> Result<(String, ServerOptions), ProbeError> {
+        let query = url.split_once('?').map_or("", |(_, query)| query);
+        let mut target = None;
+        let mut module = None;
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            match key.as_ref() {
+                "target" => target = Some(value.into_owned()),
+                "module" => module = Some(value.into_owned()),
+                _ => {},
+            }
+        }
+        let target = target.ok_or(ProbeError::MissingTarget)?;
+        let options = match module {
+            None => self.default.clone(),
+            Some(module) => self
+                .modules
+                .read()
+                .ok()
+                .and_then(|modules| modules.get(&module).cloned())
+                .ok_or(ProbeError::UnknownModule(module))?,
+        };
+        Ok((target, options))
+    }
+
+    async fn probe(
+        &self,
+        target: &str,
+        options: ServerOptions,
+    ) -> anyhow::Result<String> {
+        let metrics = Arc::new(Metrics::new(String::new(), &self.buckets));
+        let start = Instant::now();
+        let success = Self::query(target, options, &metrics)
+            .await
+            .unwrap_or_else(|err| {
+                tracing::debug!(target, "Probe failed: {:#}", err);
+                false
+            });
+        metrics.encode_probe(success, start.elapsed())
+    }
+
+    async fn query(
+        target: &str,
+        mut options: ServerOptions,
+        metrics: &Arc<Metrics>,
+    ) -> anyhow::Result<bool> {
+        let addr = lookup_host(target)
+            .await?
+            .next()
+            .ok_or_else(|| anyhow!("Can't resolve {target}"))?;
+        options.host = target
+            .parse::<SocketAddr>()
+            .is_err()
+            .then(|| target.to_owned());
+        let bind_addr: SocketAddr = if addr.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = Arc::new(UdpSocket::bind(bind_addr).await?);
+        metrics.set_server_labels(addr, &options);
+
+        let (tx_packet, rx_packet) = mpsc::channel::<Vec<u8>>(1);
+        // Probes don't receive logs
+        let (_tx_log, rx_log) = mpsc::channel(1);
+        let (_tx_addr, rx_addr) = watch::channel(addr);
+        let reader =
+            tokio::spawn(forward(Arc::clone(&socket), addr, tx_packet));
+        let mut server = GameServer::new(
+            rx_addr,
+            options.clone(),
+            time::interval(options.interval),
+            rx_packet,
+            rx_log,
+            socket,
+            Arc::clone(metrics),
+        );
+        let success = server.probe().await;
+        reader.abort();
+        Ok(success)
+    }
+}
+
+/// Passes replies of the probed server to its worker
+async fn forward(
+    socket: Arc<UdpSocket>,
+    addr: SocketAddr,
+    packets: Sender<Vec<u8>>,
+) {
+    let mut buf = [0; MAX_REPLY_SIZE];
+    while let Ok((amt, src)) = socket.recv_from(&mut buf).await {
+        if src != addr {
+            continue;
+        }
+        let Some(buf) = buf.get(..amt) else {
+            continue;
+        };
+        if packets.send(Vec::from(buf)).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use tokio::net::UdpSocket;
+
+    use super::{ProbeError, Prober};
+    use crate::config::ServerOptions;
+
+    fn options() -> ServerOptions {
+        ServerOptions {
+            interval: Duration::from_secs(10),
+            reply_timeout: Duration::from_millis(300),
+            ..ServerOptions::default()
+        }
+    }
+
+    /// Server which answers every `A2S_INFO` query
+    async fn fake_server() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let mut reply = b"\xFF\xFF\xFF\xFF\x49\x30".to_vec();
+        reply.extend(b"Server\0de_dust2\0cstrike\0Counter-Strike\0");
+        reply.extend(b"\x0A\x00\x05\x20\x02dl\x01\x011.1.2.7/Stdio\0");
+        tokio::spawn(async move {
+            let mut buf = [0; 1400];
+            while let Ok((_, src)) = socket.recv_from(&mut buf).await {
+                if buf.starts_with(b"\xFF\xFF\xFF\xFFTSource Engine Query") {
+                    socket.send_to(&reply, src).await.unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    /// Value of the sample which line starts with `name`
+    fn sample(text: &str, name: &str) -> Option<f64> {
+        text.lines()
+            .find(|line| line.starts_with(name))
+            .and_then(|line| line.rsplit_once(' '))
+            .and_then(|(_, value)| value.parse().ok())
+    }
+
+    #[tokio::test]
+    async fn probe_replying_server() {
+        let addr = fake_server().await;
+        let prober = Prober::new(options(), vec![0.1, 1.0]);
+
+        let text = prober
+            .probe(&addr.to_string(), options())
+            .await
+            .expect("probe should encode");
+        assert_eq!(sample(&text, "probe_success "), Some(1.0));
+        let duration = sample(&text, "probe_duration_seconds ")
+            .expect("duration should be exported");
+        assert!(duration < 0.3, "{duration}");
+        assert!(text.contains("hlds_players{"));
+    }
+
+    #[tokio::test]
+    async fn probe_silent_target() {
+        // Bound so the queries are neither answered nor rejected
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let prober = Prober::new(options(), vec![0.1, 1.0]);
+
+        let text = prober
+            .probe(&addr.to_string(), options())
+            .await
+            .expect("probe should encode");
+        assert_eq!(sample(&text, "probe_success "), Some(0.0));
+        let duration = sample(&text, "probe_duration_seconds ")
+            .expect("duration should be exported");
+        assert!(duration >= 0.3, "{duration}");
+        drop(socket);
+    }
+
+    #[tokio::test]
+    async fn parse_probe_params() {
+        let prober = Prober::new(ServerOptions::default(), vec![]);
+        prober.set_modules(HashMap::from([(
+            "full".to_owned(),
+            ServerOptions {
+                reply_timeout: Duration::from_secs(2),
+                query_players: true,
+                ..ServerOptions::default()
+            },
+        )]));
+
+        let (target, options) = prober
+            .params("/probe?target=127.0.0.1%3A27015")
+            .expect("params should parse");
+        assert_eq!(target, "127.0.0.1:27015");
+        assert!(!options.query_players);
+
+        let (_, options) = prober
+            .params("/probe?target=localhost:27015&module=full")
+            .expect("params should parse");
+        assert!(options.query_players);
+
+        assert!(matches!(
+            prober.params("/probe?module=full"),
+            Err(ProbeError::MissingTarget)
+        ));
+        assert!(matches!(
+            prober.params("/probe?target=localhost:27015&module=none"),
+            Err(ProbeError::UnknownModule(_))
+        ));
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
+
+static CHALLENGE_RCON: &[u8] = b"\xFF\xFF\xFF\xFFchallenge rcon\n";
+static CHALLENGE_RCON_REPLY: &[u8] = b"challenge rcon ";
+pub const S2C_RCON_CHALLENGE: u8 = b'c';
+pub const A2A_PRINT: u8 = b'l';
+
+/// Performance counters printed by the `stats` command
+#[derive(Debug, PartialEq)]
+pub struct Stats {
+    pub cpu: f64,
+    pub net_in: f64,
+    pub net_out: f64,
+    pub uptime: f64,
+    pub fps: f64,
+}
+
+/// Player line of the `status` command
+#[derive(Debug, PartialEq, Eq)]
+pub struct PlayerStatus {
+    pub name: String,
+    pub ping: u32,
+}
+
+/// Text printed by the server in reply to a command
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    Stats(Stats),
+    Status(Vec<PlayerStatus>),
+    Denied(String),
+    Other,
+}
+
+/// Collects `status` output which servers print in several packets
+#[derive(Debug, Default)]
+pub struct PrintBuffer {
+    status: Option<String>,
+}
+
+impl PrintBuffer {
+    /// Returns printed text once it is complete. `status` output is held
+    /// back until its `N users` line, a new `status` output drops the
+    /// previous one which tail was lost
+    pub fn push(&mut self, payload: &[u8]) -> Option<String> {
+        let text = String::from_utf8_lossy(payload);
+        let text = text.trim_end_matches('\0');
+        if text.starts_with("hostname") {
+            self.status = Some(String::new());
+        } else if text.starts_with("CPU")
+            || text.starts_with("Bad rcon_password")
+            || text.starts_with("Bad challenge")
+        {
+            return Some(text.to_owned());
+        }
+        let Some(status) = &mut self.status else {
+            return Some(text.to_owned());
+        };
+        status.push_str(text);
+        if status.lines().any(is_status_end) {
+            self.status.take()
+        } else {
+            None
+        }
+    }
+}
+
+/// Last line of `status` output, e.g. `3 users`
+fn is_status_end(line: &str) -> bool {
+    line.trim()
+        .strip_suffix(" users")
+        .is_some_and(|count| count.parse::<u32>().is_ok())
+}
+
+pub const fn challenge_request() -> &'static [u8] {
+    CHALLENGE_RCON
+}
+
+/// Extracts the challenge from a `challenge rcon <number>` reply
+pub fn parse_challenge(payload: &[u8]) -> Option<String> {
+    let challenge = payload.strip_prefix(CHALLENGE_RCON_REPLY)?;
+    let challenge = std::str::from_utf8(challenge)
+        .ok()?
+        .trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    (!challenge.is_empty() && challenge.chars().all(|c| c.is_ascii_digit()))
+        .then(|| challenge.to_owned())
+}
+
+/// Builds a `rcon <challenge> "<password>" <command>` request
+pub fn command(challenge: &str, password: &str, command: &str) -> Vec<u8> {
+    let mut msg = Vec::from(b"\xFF\xFF\xFF\xFF".as_slice());
+    msg.extend(format!("rcon {challenge} \"{password}\" {command}\n").bytes());
+    msg
+}
+
+/// Recognizes output of `stats` and `status` commands and errors
+pub fn parse_print(payload: &[u8]) -> anyhow::Result<Reply> {
+    let text = String::from_utf8_lossy(payload);
+    let text = text.trim_end_matches('\0');
+    let mut lines = text.lines().map(str::trim);
+    if text.starts_with("Bad rcon_password")
+        || text.starts_with("Bad challenge")
+    {
+        return Ok(Reply::Denied(text.trim().to_owned()));
+    }
+    if let Some(header) = lines.find(|line| line.starts_with("CPU")) {
+        let values =
+            lines.next().ok_or_else(|| anyhow!("Stats are missing"))?;
+        return parse_stats(header, values).map(Reply::Stats);
+    }
+    if text.lines().any(|line| line.starts_with("hostname")) {
+        return Ok(Reply::Status(
+            text.lines().filter_map(parse_player_line).collect(),
+        ));
+    }
+    Ok(Reply::Other)
+}
+
+fn parse_stats(header: &str, values: &str) -> anyhow::Result<Stats> {
+    let values: HashMap<_, _> = header
+        .split_whitespace()
+        .zip(values.split_whitespace())
+        .collect();
+    let value = |name: &str| -> anyhow::Result<f64> {
+        let Some(value) = values.get(name) else {
+            bail!("Stats have no {name} column");
+        };
+        value
+            .parse()
+            .map_err(|e| anyhow!("Invalid {name} value {value}: {e}"))
+    };
+    Ok(Stats {
+        cpu: value("CPU")?,
+        net_in: value("In")?,
+        net_out: value("Out")?,
+        uptime: value("Uptime")?,
+        fps: value("FPS")?,
+    })
+}
+
+/// Parses `# 1 "name" userid uniqueid frag time ping loss adr`, bots are
+/// skipped as their ping is meaningless
+fn parse_player_line(line: &str) -> Option<PlayerStatus> {
+    let line = line.trim().strip_prefix('#')?;
+    let (slot, rest) = line.trim_start().split_once(char::is_whitespace)?;
+    slot.parse::<u32>().ok()?;
+    let rest = rest.trim_start().strip_prefix('"')?;
+    let (name, rest) = rest.rsplit_once('"')?;
+    let fields: Vec<_> = rest.split_whitespace().collect();
+    let [_userid, uniqueid, _frag, _time, ping, ..] = fields.as_slice() else {
+        return None;
+    };
+    if *uniqueid == "BOT" || name.is_empty() {
+        return None;
+    }
+    Some(PlayerStatus {
+        name: name.to_owned(),
+        ping: ping.parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        command, parse_challenge, parse_print, PlayerStatus, PrintBuffer,
+        Reply, Stats,
+    };
+
+    #[test]
+    fn parse_rcon_challenge() {
+        assert_eq!(
+            parse_challenge(b"challenge rcon 1234567890\n\0"),
+            Some("1234567890".to_owned())
+        );
+        assert_eq!(parse_challenge(b"challenge rcon \n"), None);
+        assert_eq!(parse_challenge(b"challenge something"), None);
+        assert_eq!(
+            command("123", "secret", "stats"),
+            b"\xFF\xFF\xFF\xFFrcon 123 \"secret\" stats\n"
+        );
+    }
+
+    #[test]
+    fn parse_stats_output() {
+        let reply = parse_print(
+            b"CPU   In    Out   Uptime  Users   FPS    Players\n \
+              12.50  3.21  7.80     245     4  99.98       2\n\0",
+        )
+        .expect("stats should parse");
+        assert_eq!(
+            reply,
+            Reply::Stats(Stats {
+                cpu: 12.5,
+                net_in: 3.21,
+                net_out: 7.8,
+                uptime: 245.0,
+                fps: 99.98,
+            })
+        );
+        assert!(parse_print(b"CPU   In    Out\n").is_err());
+        assert!(matches!(
+            parse_print(b"Bad rcon_password.\n"),
+            Ok(Reply::Denied(_))
+        ));
+    }
+
+    #[test]
+    fn parse_status_output() {
+        let reply = parse_print(
+            br#"hostname:  Public
+version :  48/1.1.2.7/Stdio 8684 secure  (10)
+tcp/ip  :  127.0.0.1:27015
+map     :  de_dust2 at: 0 x, 0 y, 0 z
+players :  3 active (32 max)
+
+#      name userid uniqueid frag time ping loss adr
+# 1 "alice the #1" 12 STEAM_0:1:1234 5 10:32 48 0 10.0.0.5:27005
+# 2 "bot" 13 BOT 0 1:00:12 0 0
+#10 "bob" 14 STEAM_0:0:42 -1 05:01 112 2 10.0.0.6:27005
+3 users
+"#,
+        )
+        .expect("status should parse");
+        assert_eq!(
+            reply,
+            Reply::Status(vec![
+                PlayerStatus {
+                    name: "alice the #1".to_owned(),
+                    ping: 48,
+                },
+                PlayerStatus {
+                    name: "bob".to_owned(),
+                    ping: 112,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn reassemble_status_output() {
+        let mut buffer = PrintBuffer::default();
+        let first =
+            b"hostname:  Public\nmap     :  de_dust2 at: 0 x, 0 y, 0 z\n\
+            #      name userid uniqueid frag time ping loss adr\n\
+            # 1 \"alice\" 12 STEAM_0:1:1234 5 10:32 48 0 10.0.0.5:27005\n\0";
+        let second =
+            b"# 2 \"bob\" 14 STEAM_0:0:42 -1 05:01 112 2 10.0.0.6:27005\n\
+            2 users\n\0";
+        assert_eq!(buffer.push(first), None);
+        // Other replies aren't held back while status output is collected
+        assert_eq!(
+            buffer.push(b"Bad challenge.\n\0").as_deref(),
+            Some("Bad challenge.\n")
+        );
+        let output = buffer.push(second).expect("status should be complete");
+        match parse_print(output.as_bytes()) {
+            Ok(Reply::Status(players)) => assert_eq!(players.len(), 2),
+            reply => panic!("unexpected reply {reply:?}"),
+        }
+
+        // Status output which tail was lost is dropped
+        assert_eq!(buffer.push(first), None);
+        assert_eq!(buffer.push(first), None);
+        let output = buffer.push(second).expect("status should be complete");
+        assert_eq!(output.matches("alice").count(), 1);
+        assert_eq!(
+            buffer.push(b"unknown command\n").as_deref(),
+            Some("unknown command\n")
+        );
+    }
+}
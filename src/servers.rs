@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+
+use anyhow::anyhow;
+use tokio::net::lookup_host;
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::{self, Sender};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::config::ServerOptions;
+use crate::events::ServerEvent;
+use crate::history::Recorder;
+use crate::hlds::GameServer;
+use crate::logs::LogEvent;
+use crate::metrics::Metrics;
+
+/// Channels of running game servers by address used to route replies
+pub type Routes = Arc<RwLock<HashMap<SocketAddr, Route>>>;
+
+/// Channels of a single game server
+#[derive(Clone)]
+pub struct Route {
+    pub packets: Sender<Vec<u8>>,
+    pub logs: Sender<LogEvent>,
+}
+
+/// Log lines come in bursts, e.g. at the end of a round
+const LOG_BUFFER: usize = 64;
+
+struct Running {
+    addr: SocketAddr,
+    options: ServerOptions,
+    tx_addr: watch::Sender<SocketAddr>,
+    handle: JoinHandle<()>,
+}
+
+/// Starts and stops `GameServer` workers as configuration changes
+pub struct Servers {
+    socket: Arc<UdpSocket>,
+    metrics: Arc<Metrics>,
+    history: Option<Recorder>,
+    events: broadcast::Sender<ServerEvent>,
+    routes: Routes,
+    /// Workers by configured address, so a hostname which resolves to
+    /// another record of round-robin DNS keeps its worker
+    running: HashMap<String, Running>,
+    /// Servers which addresses couldn't be resolved yet or were already
+    /// monitored
+    unresolved: Vec<(String, ServerOptions)>,
+}
+
+impl Servers {
+    pub fn new(
+        socket: Arc<UdpSocket>,
+        metrics: Arc<Metrics>,
+        history: Option<Recorder>,
+        events: broadcast::Sender<ServerEvent>,
+    ) -> Self {
+        Self {
+            socket,
+            metrics,
+            history,
+            events,
+            routes: Arc::new(RwLock::new(HashMap::new())),
+            running: HashMap::new(),
+            unresolved: vec![],
+        }
+    }
+
+    pub fn routes(&self) -> Routes {
+        Arc::clone(&self.routes)
+    }
+
+    /// Brings running workers in line with the given servers, restarting
+    /// the ones which options have changed
+    pub async fn update(&mut self, servers: Vec<(String, ServerOptions)>) {
+        let stale: Vec<_> = self
+            .running
+            .iter()
+            .filter(|(spec, running)| {
+                !servers.iter().any(|(wanted, options)| {
+                    wanted == *spec && *options == running.options
+                })
+            })
+            .map(|(spec, _)| spec.clone())
+            .collect();
+        for spec in stale {
+            self.stop(&spec);
+        }
+
+        self.unresolved = servers
+            .into_iter()
+            .filter(|(spec, _)| !self.running.contains_key(spec))
+            .collect();
+        self.start_unresolved().await;
+    }
+
+    /// Starts servers which addresses resolve, others are tried again on
+    /// the next resolve. So are servers which address is already monitored,
+    /// as the other server may move or be removed later
+    async fn start_unresolved(&mut self) {
+        for (spec, options) in std::mem::take(&mut self.unresolved) {
+            match resolve(&spec).await {
+                Ok(addr) if self.is_monitored(addr) => {
+                    tracing::warn!(
+                        "Can't start server {}: {} is already monitored",
+                        spec,
+                        addr
+                    );
+                    self.unresolved.push((spec, options));
+                },
+                Ok(addr) => self.start(spec, addr, options),
+                Err(e) => {
+                    tracing::warn!("Can't resolve {}: {}", spec, e);
+                    self.unresolved.push((spec, options));
+                },
+            }
+        }
+    }
+
+    fn is_monitored(&self, addr: SocketAddr) -> bool {
+        self.running.values().any(|running| running.addr == addr)
+    }
+
+    fn start(
+        &mut self,
+        spec: String,
+        addr: SocketAddr,
+        options: ServerOptions,
+    ) {
+        tracing::info!("Starting server {}", addr);
+        self.metrics.set_server_labels(addr, &options);
+        let (tx_packet, rx_packet) = mpsc::channel::<Vec<u8>>(1);
+        let (tx_log, rx_log) = mpsc::channel::<LogEvent>(LOG_BUFFER);
+        let (tx_addr, rx_addr) = watch::channel(addr);
+        let mut interval = time::interval(options.interval);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+        let mut server = GameServer::new(
+            rx_addr,
+            options.clone(),
+            interval,
+            rx_packet,
+            rx_log,
+            Arc::clone(&self.socket),
+            Arc::clone(&self.metrics),
+        )
+        .with_history(self.history.clone())
+        .with_events(Some(self.events.clone()));
+        let handle = tokio::spawn(async move {
+            server.process().await;
+        });
+        match self.routes.write() {
+            Ok(mut routes) => {
+                routes.insert(
+                    addr,
+                    Route {
+                        packets: tx_packet,
+                        logs: tx_log,
+                    },
+                );
+            },
+            Err(err) => tracing::warn!("Can't update routes: {}", err),
+        }
+        self.running.insert(
+            spec,
+            Running {
+                addr,
+                options,
+                tx_addr,
+                handle,
+            },
+        );
+    }
+
+    fn stop(&mut self, spec: &str) {
+        let Some(running) = self.running.remove(spec) else {
+            return;
+        };
+        tracing::info!("Stopping server {}", running.addr);
+        running.handle.abort();
+        match self.routes.write() {
+            Ok(mut routes) => {
+                routes.remove(&running.addr);
+            },
+            Err(err) => tracing::warn!("Can't update routes: {}", err),
+        }
+        self.metrics.remove_server(running.addr);
+    }
+
+    /// Resolves hostnames of running servers again and moves servers which
+    /// address has changed, then starts servers which didn't resolve before
+    pub async fn resolve(&mut self) {
+        let hosts: Vec<_> = self
+            .running
+            .iter()
+            .filter_map(|(spec, running)| {
+                running
+                    .options
+                    .host
+                    .clone()
+                    .map(|host| (spec.clone(), host))
+            })
+            .collect();
+        for (spec, host) in hosts {
+            let resolved: Vec<_> = match lookup_host(&host).await {
+                Ok(resolved) => resolved.collect(),
+                Err(e) => {
+                    tracing::warn!("Can't resolve {}: {}", host, e);
+                    continue;
+                },
+            };
+            let Some(running) = self.running.get(&spec) else {
+                continue;
+            };
+            if resolved.contains(&running.addr) {
+                continue;
+            }
+            let Some(new_addr) = resolved.first().copied() else {
+                continue;
+            };
+            self.migrate(&spec, new_addr);
+        }
+        self.start_unresolved().await;
+    }
+
+    fn migrate(&mut self, spec: &str, new_addr: SocketAddr) {
+        if self.is_monitored(new_addr) {
+            tracing::warn!(
+                "Can't move server {} to {}: address is already monitored",
+                spec,
+                new_addr
+            );
+            return;
+        }
+        let Some(running) = self.running.get_mut(spec) else {
+            return;
+        };
+        let addr = running.addr;
+        tracing::info!("Server {} moved to {}", addr, new_addr);
+        self.metrics.rename_server(addr, new_addr);
+        match self.routes.write() {
+            Ok(mut routes) => {
+                if let Some(route) = routes.remove(&addr) {
+                    routes.insert(new_addr, route);
+                }
+            },
+            Err(err) => tracing::warn!("Can't update routes: {}", err),
+        }
+        running.tx_addr.send_replace(new_addr);
+        running.addr = new_addr;
+    }
+}
+
+/// First address of a server, IP addresses are used without a lookup
+async fn resolve(spec: &str) -> anyhow::Result<SocketAddr> {
+    if let Ok(addr) = spec.parse() {
+        return Ok(addr);
+    }
+    lookup_host(spec)
+        .await?
+        .next()
+        .ok_or_else(|| anyhow!("No addresses found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::net::UdpSocket;
+    use tokio::sync::broadcast;
+
+    use super::{Running, Servers};
+    use crate::config::ServerOptions;
+    use crate::metrics::Metrics;
+
+    async fn servers() -> Servers {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        Servers::new(
+            Arc::new(socket),
+            Arc::new(Metrics::new(String::new(), &[0.1, 1.0])),
+            None,
+            broadcast::channel(1).0,
+        )
+    }
+
+    fn options(interval: u64) -> ServerOptions {
+        ServerOptions {
+            interval: Duration::from_secs(interval),
+            reply_timeout: Duration::from_secs(5),
+            up_threshold: Duration::from_secs(interval),
+            ..ServerOptions::default()
+        }
+    }
+
+    fn server(spec: &str) -> (String, ServerOptions) {
+        (spec.to_string(), options(10))
+    }
+
+    fn running<'a>(servers: &'a Servers, spec: &str) -> &'a Running {
+        servers.running.get(spec).expect("server should be running")
+    }
+
+    fn routed(servers: &Servers, addr: &str) -> bool {
+        let addr: SocketAddr = addr.parse().unwrap();
+        servers.routes.read().unwrap().contains_key(&addr)
+    }
+
+    #[tokio::test]
+    async fn add_and_remove_servers() {
+        let mut servers = servers().await;
+        servers
+            .update(vec![server("127.0.0.1:27015"), server("127.0.0.1:27016")])
+            .await;
+        assert_eq!(servers.running.len(), 2);
+        assert!(routed(&servers, "127.0.0.1:27015"));
+        assert!(routed(&servers, "127.0.0.1:27016"));
+
+        servers.update(vec![server("127.0.0.1:27016")]).await;
+        assert_eq!(servers.running.len(), 1);
+        assert!(!routed(&servers, "127.0.0.1:27015"));
+        assert!(routed(&servers, "127.0.0.1:27016"));
+    }
+
+    #[tokio::test]
+    async fn restart_servers_with_new_options() {
+        let mut servers = servers().await;
+        servers
+            .update(vec![server("127.0.0.1:27015"), server("127.0.0.1:27016")])
+            .await;
+        // Address channels are closed when their workers are replaced
+        let kept = running(&servers, "127.0.0.1:27015").tx_addr.subscribe();
+        let restarted =
+            running(&servers, "127.0.0.1:27016").tx_addr.subscribe();
+
+        servers
+            .update(vec![
+                server("127.0.0.1:27015"),
+                ("127.0.0.1:27016".to_string(), options(30)),
+            ])
+            .await;
+        assert!(kept.has_changed().is_ok());
+        assert!(restarted.has_changed().is_err());
+        assert_eq!(running(&servers, "127.0.0.1:27016").options, options(30));
+        assert!(routed(&servers, "127.0.0.1:27016"));
+    }
+
+    #[tokio::test]
+    async fn migrate_server() {
+        let mut servers = servers().await;
+        servers.update(vec![server("127.0.0.1:27015")]).await;
+        let rx_addr = running(&servers, "127.0.0.1:27015").tx_addr.subscribe();
+
+        servers.migrate("127.0.0.1:27015", "127.0.0.2:27015".parse().unwrap());
+        let new_addr: SocketAddr = "127.0.0.2:27015".parse().unwrap();
+        assert_eq!(running(&servers, "127.0.0.1:27015").addr, new_addr);
+        assert_eq!(*rx_addr.borrow(), new_addr);
+        assert!(!routed(&servers, "127.0.0.1:27015"));
+        assert!(routed(&servers, "127.0.0.2:27015"));
+    }
+
+    #[tokio::test]
+    async fn keep_address_on_collision() {
+        let mut servers = servers().await;
+        servers
+            .update(vec![server("127.0.0.1:27015"), server("127.0.0.1:27016")])
+            .await;
+
+        servers.migrate("127.0.0.1:27015", "127.0.0.1:27016".parse().unwrap());
+        let addr: SocketAddr = "127.0.0.1:27015".parse().unwrap();
+        assert_eq!(running(&servers, "127.0.0.1:27015").addr, addr);
+        assert!(routed(&servers, "127.0.0.1:27015"));
+        assert!(routed(&servers, "127.0.0.1:27016"));
+    }
+
+    #[tokio::test]
+    async fn retry_server_with_monitored_address() {
+        let addr = super::resolve("localhost:27015").await.unwrap();
+        let mut servers = servers().await;
+        servers
+            .update(vec![server(&addr.to_string()), server("localhost:27015")])
+            .await;
+        assert_eq!(servers.running.len(), 1);
+        assert_eq!(servers.unresolved.len(), 1);
+
+        // Once the address is free, the server starts on the next resolve
+        servers.stop(&addr.to_string());
+        servers.resolve().await;
+        assert_eq!(running(&servers, "localhost:27015").addr, addr);
+        assert!(servers.unresolved.is_empty());
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::logs::Player;
+
+/// Time a player has been seen in a session
+#[derive(Debug, Clone, Copy)]
+struct Session {
+    start: Instant,
+    seen: Instant,
+}
+
+/// Source of sessions, logs identify players by Steam ID while player
+/// lists only have names
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    PlayerList,
+    Logs,
+}
+
+/// Players which joined or left since the previous player list
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ListChanges {
+    pub joined: Vec<String>,
+    /// Names of players which left with lengths of their sessions
+    pub left: Vec<(String, Duration)>,
+}
+
+/// Sessions of players on a server with recently seen players for unique
+/// player counts. At most `limit` players are kept, the least recently
+/// seen ones are forgotten first
+#[derive(Debug)]
+pub struct Sessions {
+    limit: usize,
+    source: Source,
+    active: HashMap<String, Session>,
+    /// Sessions of the last player list by name, continued once the
+    /// players are seen in logs
+    listed: HashMap<String, Session>,
+    last_seen: HashMap<String, Instant>,
+}
+
+impl Sessions {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            source: Source::PlayerList,
+            active: HashMap::new(),
+            listed: HashMap::new(),
+            last_seen: HashMap::new(),
+        }
+    }
+
+    /// Updates sessions from an `A2S_PLAYER` list of names with connection
+    /// times. Lists are ignored once sessions come from logs
+    pub fn observe_list(
+        &mut self,
+        players: &[(&str, Duration)],
+        now: Instant,
+    ) -> ListChanges {
+        let mut changes = ListChanges::default();
+        if self.source == Source::Logs {
+            return changes;
+        }
+        for (name, connected) in players {
+            if let Some(session) = self.active.get_mut(*name) {
+                session.seen = now;
+            } else if self.active.len() < self.limit {
+                let start = now.checked_sub(*connected).unwrap_or(now);
+                self.active
+                    .insert((*name).to_owned(), Session { start, seen: now });
+                changes.joined.push((*name).to_owned());
+            }
+            self.see(name, now);
+        }
+        self.active.retain(|name, session| {
+            let present = players.iter().any(|(n, _)| n == name);
+            if !present {
+                let duration = session.seen.duration_since(session.start);
+                changes.left.push((name.clone(), duration));
+            }
+            present
+        });
+        changes.left.sort();
+        changes
+    }
+
+    /// Starts a session of a player which connected according to logs,
+    /// returns whether the player wasn't connected already
+    pub fn connect(&mut self, player: &Player, now: Instant) -> bool {
+        let Some(id) = player_id(player) else {
+            return false;
+        };
+        self.use_logs();
+        self.see_in_logs(player, id, now);
+        if self.active.contains_key(id) {
+            return false;
+        }
+        if let Some(session) = self.listed.remove(&player.name) {
+            self.active.insert(id.to_owned(), session);
+            return false;
+        }
+        if self.active.len() >= self.limit {
+            return false;
+        }
+        self.active.insert(
+            id.to_owned(),
+            Session {
+                start: now,
+                seen: now,
+            },
+        );
+        true
+    }
+
+    /// Ends a session of a player which disconnected according to logs,
+    /// returns its duration if the player is known from logs or the last
+    /// player list
+    pub fn disconnect(
+        &mut self,
+        player: &Player,
+        now: Instant,
+    ) -> Option<Duration> {
+        let id = player_id(player)?;
+        self.use_logs();
+        self.see_in_logs(player, id, now);
+        let session = self
+            .active
+            .remove(id)
+            .or_else(|| self.listed.remove(&player.name))?;
+        Some(now.duration_since(session.start))
+    }
+
+    /// Number of players seen within the window, players seen before the
+    /// longest window are forgotten
+    pub fn unique(
+        &mut self,
+        windows: &[Duration],
+        now: Instant,
+    ) -> Vec<(Duration, usize)> {
+        // Connected players are seen until their sessions end
+        for id in self.active.keys().chain(self.listed.keys()) {
+            self.last_seen.insert(id.clone(), now);
+        }
+        if let Some(longest) = windows.iter().max() {
+            self.last_seen
+                .retain(|_, seen| now.duration_since(*seen) <= *longest);
+        }
+        windows
+            .iter()
+            .map(|window| {
+                let count = self
+                    .last_seen
+                    .values()
+                    .filter(|seen| now.duration_since(**seen) <= *window)
+                    .count();
+                (*window, count)
+            })
+            .collect()
+    }
+
+    /// Players of previous lists are kept by name as they can't be matched
+    /// to Steam IDs in logs until they are seen there
+    fn use_logs(&mut self) {
+        if self.source == Source::PlayerList {
+            self.source = Source::Logs;
+            self.listed = std::mem::take(&mut self.active);
+        }
+    }
+
+    /// Players seen in lists by name are seen by Steam ID in logs, their
+    /// names are dropped so they are counted once
+    fn see_in_logs(&mut self, player: &Player, id: &str, now: Instant) {
+        if id != player.name {
+            self.last_seen.remove(&player.name);
+        }
+        self.see(id, now);
+    }
+
+    fn see(&mut self, id: &str, now: Instant) {
+        if let Some(seen) = self.last_seen.get_mut(id) {
+            *seen = now;
+            return;
+        }
+        if self.last_seen.len() >= self.limit {
+            let oldest = self
+                .last_seen
+                .iter()
+                .min_by_key(|(_, seen)| **seen)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                self.last_seen.remove(&oldest);
+            }
+        }
+        self.last_seen.insert(id.to_owned(), now);
+    }
+}
+
+/// Identity of a player in logs, bots are skipped and players without a
+/// Steam ID are identified by name
+fn player_id(player: &Player) -> Option<&str> {
+    match player.steam_id.as_str() {
+        "BOT" => None,
+        "STEAM_ID_LAN" | "STEAM_ID_PENDING" | "" => Some(&player.name),
+        steam_id => Some(steam_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{ListChanges, Sessions};
+    use crate::logs::Player;
+
+    const fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn player(name: &str, steam_id: &str) -> Player {
+        Player {
+            name: name.to_owned(),
+            uid: "2".to_owned(),
+            steam_id: steam_id.to_owned(),
+            team: String::new(),
+        }
+    }
+
+    fn changes(joined: &[&str], left: &[(&str, u64)]) -> ListChanges {
+        ListChanges {
+            joined: joined.iter().map(|name| (*name).to_owned()).collect(),
+            left: left
+                .iter()
+                .map(|(name, duration)| ((*name).to_owned(), secs(*duration)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn track_player_list_sessions() {
+        let start = Instant::now();
+        let mut sessions = Sessions::new(10);
+        assert_eq!(
+            sessions
+                .observe_list(&[("alice", secs(60)), ("bob", secs(0))], start),
+            changes(&["alice", "bob"], &[])
+        );
+        assert_eq!(
+            sessions.observe_list(&[("alice", secs(90))], start + secs(30)),
+            changes(&[], &[("bob", 0)])
+        );
+        assert_eq!(
+            sessions.observe_list(&[], start + secs(60)),
+            changes(&[], &[("alice", 90)])
+        );
+        assert_eq!(
+            sessions.unique(&[secs(10), secs(3600)], start + secs(60)),
+            [(secs(10), 0), (secs(3600), 2)]
+        );
+        assert_eq!(
+            sessions.unique(&[secs(3600)], start + secs(3625)),
+            [(secs(3600), 1)]
+        );
+    }
+
+    #[test]
+    fn track_log_sessions() {
+        let start = Instant::now();
+        let mut sessions = Sessions::new(2);
+        sessions.observe_list(&[("alice", secs(60))], start);
+        let alice = player("alice", "STEAM_0:1:2");
+        // Alice continues her session of the player list
+        assert!(!sessions.connect(&alice, start));
+        assert!(!sessions.connect(&alice, start + secs(1)));
+        assert!(!sessions.connect(&player("bot", "BOT"), start));
+        // Player lists are ignored once sessions come from logs
+        assert_eq!(
+            sessions.observe_list(&[("bob", secs(5))], start + secs(5)),
+            ListChanges::default()
+        );
+        assert_eq!(
+            sessions.disconnect(&alice, start + secs(120)),
+            Some(secs(180))
+        );
+        assert_eq!(
+            sessions.disconnect(&player("bob", "STEAM_ID_LAN"), start),
+            None
+        );
+
+        // The least recently seen player is forgotten over the limit
+        assert!(sessions.connect(&player("carol", "STEAM_0:1:4"), start));
+        assert_eq!(
+            sessions.unique(&[secs(3600)], start + secs(120)),
+            [(secs(3600), 2)]
+        );
+    }
+
+    #[test]
+    fn count_players_once_after_switch_to_logs() {
+        let start = Instant::now();
+        let mut sessions = Sessions::new(10);
+        sessions.observe_list(&[("alice", secs(60)), ("bob", secs(5))], start);
+        assert!(!sessions.connect(&player("alice", "STEAM_0:1:2"), start));
+        assert_eq!(
+            sessions
+                .disconnect(&player("bob", "STEAM_0:1:3"), start + secs(1)),
+            Some(secs(6))
+        );
+        assert_eq!(
+            sessions.unique(&[secs(3600)], start + secs(2)),
+            [(secs(3600), 2)]
+        );
+        // Players which connected before sessions were tracked are unknown
+        assert_eq!(
+            sessions.disconnect(&player("carol", "STEAM_0:1:4"), start),
+            None
+        );
+    }
+}
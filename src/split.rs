@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+static HEADER: &[u8] = b"\xFF\xFF\xFF\xFF";
+
+const SPLIT_HEADER_LENGTH: usize = 8;
+const GOLDSRC_HEADER_LENGTH: usize = 9;
+const SOURCE_HEADER_LENGTH: usize = 12;
+/// Source engine marks bzip2 compressed responses with the highest bit
+const COMPRESSED_FLAG: u32 = 0x8000_0000;
+/// First fragment of a compressed response has the decompressed size and
+/// checksum before the bzip2 stream
+const COMPRESSION_HEADER_LENGTH: usize = 8;
+static BZIP2_MAGIC: &[u8] = b"BZh";
+/// Upper bound of fragments kept for one response, `GoldSrc` can't address
+/// more than 15 and Source servers never send that many
+const MAX_FRAGMENTS: usize = 32;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SplitError {
+    #[error("Split packet header is truncated")]
+    Truncated,
+    #[error("Compressed split packets are not supported")]
+    Compressed,
+    #[error("Too many fragments for a split packet")]
+    TooManyFragments,
+    #[error("Split packet was not completed in time")]
+    Timeout,
+}
+
+impl SplitError {
+    /// Short reason used as a metric label
+    pub const fn reason(&self) -> &'static str {
+        match self {
+            Self::Truncated => "truncated",
+            Self::Compressed => "compressed",
+            Self::TooManyFragments => "too_many_fragments",
+            Self::Timeout => "timeout",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SplitFormat {
+    /// Packet number in upper and total in lower nibble of a single byte
+    GoldSrc,
+    /// Separate total and number bytes followed by the fragment size
+    Source,
+}
+
+impl SplitFormat {
+    /// Returns `(number, total, payload)` of a fragment
+    fn decode(self, fragment: &[u8]) -> Option<(usize, usize, &[u8])> {
+        match self {
+            Self::GoldSrc => {
+                let byte = fragment.get(SPLIT_HEADER_LENGTH)?;
+                let payload = fragment.get(GOLDSRC_HEADER_LENGTH..)?;
+                Some(((byte >> 4).into(), (byte & 0x0F).into(), payload))
+            },
+            Self::Source => {
+                let total = fragment.get(SPLIT_HEADER_LENGTH)?;
+                let number = fragment.get(SPLIT_HEADER_LENGTH + 1)?;
+                let payload = fragment.get(SOURCE_HEADER_LENGTH..)?;
+                Some(((*number).into(), (*total).into(), payload))
+            },
+        }
+    }
+
+    /// Concatenates payloads if all fragments of the response are present
+    fn assemble(self, fragments: &[Vec<u8>]) -> Option<Vec<u8>> {
+        let mut total = None;
+        let mut slots: Vec<Option<&[u8]>> = vec![];
+        for fragment in fragments {
+            let (number, count, payload) = self.decode(fragment)?;
+            if count == 0
+                || number >= count
+                || *total.get_or_insert(count) != count
+            {
+                return None;
+            }
+            slots.resize(count, None);
+            *slots.get_mut(number)? = Some(payload);
+        }
+
+        let first = slots.first().copied().flatten()?;
+        if !first.starts_with(HEADER) {
+            return None;
+        }
+        let mut packet = vec![];
+        for slot in slots {
+            packet.extend(slot?);
+        }
+        Some(packet)
+    }
+}
+
+struct Pending {
+    started: Instant,
+    fragments: Vec<Vec<u8>>,
+}
+
+/// Reassembles split responses of both `GoldSrc` and Source formats
+pub struct SplitPackets {
+    pending: HashMap<u32, Pending>,
+    timeout: Duration,
+}
+
+impl SplitPackets {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            pending: HashMap::new(),
+            timeout,
+        }
+    }
+
+    /// Stores a fragment and returns the whole packet once it is complete
+    pub fn push(
+        &mut self,
+        packet: &[u8],
+    ) -> Result<Option<Vec<u8>>, SplitError> {
+        let id = packet
+            .get(HEADER.len()..SPLIT_HEADER_LENGTH)
+            .and_then(|id| id.try_into().ok())
+            .map(u32::from_le_bytes)
+            .ok_or(SplitError::Truncated)?;
+        if packet.len() <= GOLDSRC_HEADER_LENGTH {
+            return Err(SplitError::Truncated);
+        }
+
+        let pending = self.pending.entry(id).or_insert_with(|| Pending {
+            started: Instant::now(),
+            fragments: vec![],
+        });
+        pending.fragments.push(packet.to_vec());
+        let fragments = &pending.fragments;
+
+        let assembled = SplitFormat::GoldSrc
+            .assemble(fragments)
+            .or_else(|| SplitFormat::Source.assemble(fragments));
+        if let Some(assembled) = assembled {
+            self.pending.remove(&id);
+            return Ok(Some(assembled));
+        }
+
+        if fragments.len() > MAX_FRAGMENTS {
+            self.pending.remove(&id);
+            return Err(SplitError::TooManyFragments);
+        }
+        if id & COMPRESSED_FLAG != 0 && is_compressed(packet) {
+            self.pending.remove(&id);
+            return Err(SplitError::Compressed);
+        }
+
+        Ok(None)
+    }
+
+    /// Drops incomplete responses older than timeout and returns their count
+    pub fn expire(&mut self) -> usize {
+        let before = self.pending.len();
+        let timeout = self.timeout;
+        self.pending
+            .retain(|_, pending| pending.started.elapsed() < timeout);
+        before - self.pending.len()
+    }
+}
+
+/// Whether the fragment is the first one of a compressed Source response,
+/// `GoldSrc` fragments may have the highest bit of the ID set as well
+fn is_compressed(fragment: &[u8]) -> bool {
+    SplitFormat::Source
+        .decode(fragment)
+        .is_some_and(|(number, _, payload)| {
+            number == 0
+                && payload
+                    .get(COMPRESSION_HEADER_LENGTH..)
+                    .is_some_and(|stream| stream.starts_with(BZIP2_MAGIC))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{SplitError, SplitPackets};
+
+    fn goldsrc(id: u32, number: u8, total: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = b"\xFE\xFF\xFF\xFF".to_vec();
+        packet.extend(id.to_le_bytes());
+        packet.push(number << 4 | total);
+        packet.extend(payload);
+        packet
+    }
+
+    fn source(id: u32, number: u8, total: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = b"\xFE\xFF\xFF\xFF".to_vec();
+        packet.extend(id.to_le_bytes());
+        packet.push(total);
+        packet.push(number);
+        packet.extend(1248_u16.to_le_bytes());
+        packet.extend(payload);
+        packet
+    }
+
+    #[test]
+    fn reassemble_goldsrc_out_of_order() {
+        let mut split = SplitPackets::new(Duration::from_secs(5));
+        assert_eq!(split.push(&goldsrc(7, 1, 2, b"second")), Ok(None));
+        assert_eq!(
+            split.push(&goldsrc(7, 0, 2, b"\xFF\xFF\xFF\xFFfirst ")),
+            Ok(Some(b"\xFF\xFF\xFF\xFFfirst second".to_vec()))
+        );
+        assert_eq!(split.expire(), 0);
+    }
+
+    #[test]
+    fn reassemble_source() {
+        let mut split = SplitPackets::new(Duration::from_secs(5));
+        assert_eq!(
+            split.push(&source(3, 0, 2, b"\xFF\xFF\xFF\xFFfirst ")),
+            Ok(None)
+        );
+        assert_eq!(
+            split.push(&source(3, 1, 2, b"second")),
+            Ok(Some(b"\xFF\xFF\xFF\xFFfirst second".to_vec()))
+        );
+    }
+
+    #[test]
+    fn reject_compressed_source() {
+        let mut split = SplitPackets::new(Duration::from_secs(5));
+        let mut payload = 4096_u32.to_le_bytes().to_vec();
+        payload.extend(0x1234_5678_u32.to_le_bytes());
+        payload.extend(b"BZh9");
+        assert_eq!(split.push(&source(0x8000_0001, 1, 2, b"BZh9")), Ok(None));
+        assert_eq!(
+            split.push(&source(0x8000_0001, 0, 2, &payload)),
+            Err(SplitError::Compressed)
+        );
+    }
+
+    #[test]
+    fn reassemble_goldsrc_with_high_id_bit() {
+        let mut split = SplitPackets::new(Duration::from_secs(5));
+        // Decoded as Source, the payload would start the first fragment
+        assert_eq!(
+            split.push(&goldsrc(0x8000_0002, 1, 2, b"\x00\x00second")),
+            Ok(None)
+        );
+        assert_eq!(
+            split.push(&goldsrc(0x8000_0002, 0, 2, b"\xFF\xFF\xFF\xFFfirst ")),
+            Ok(Some(b"\xFF\xFF\xFF\xFFfirst \x00\x00second".to_vec()))
+        );
+    }
+
+    #[test]
+    fn expire_incomplete() {
+        let mut split = SplitPackets::new(Duration::ZERO);
+        assert_eq!(
+            split.push(&goldsrc(1, 0, 2, b"\xFF\xFF\xFF\xFFa")),
+            Ok(None)
+        );
+        assert_eq!(split.expire(), 1);
+    }
+}
@@ -0,0 +1,415 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time;
+
+use crate::config::{Secret, WebhookSpec};
+use crate::events::ServerEvent;
+
+/// Payloads waiting to be delivered, new ones are dropped when it's full
+const PAYLOAD_BUFFER: usize = 256;
+/// Time changes of a server are combined for by default
+const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(30);
+const DEFAULT_RETRIES: u32 = 3;
+/// Delay before the first retry, doubled for every next one
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Events sent to webhooks which don't list theirs
+const DEFAULT_EVENTS: [&str; 6] = [
+    "up",
+    "down",
+    "map_change",
+    "name_change",
+    "players_above",
+    "players_below",
+];
+
+/// Change waiting for the debounce time to pass
+#[derive(Debug)]
+struct Pending {
+    event: ServerEvent,
+    due: Instant,
+}
+
+/// Webhook with changes of servers which are not sent yet
+struct Webhook {
+    id: usize,
+    events: Vec<String>,
+    templates: BTreeMap<String, String>,
+    debounce: Duration,
+    /// Pending changes by server and topic
+    pending: HashMap<(String, String), Pending>,
+    payloads: SyncSender<String>,
+}
+
+impl Webhook {
+    /// Starts a thread which delivers payloads of the webhook
+    fn start(id: usize, spec: WebhookSpec) -> Self {
+        let (payloads, rx) = mpsc::sync_channel(PAYLOAD_BUFFER);
+        let retries = spec.retries.unwrap_or(DEFAULT_RETRIES);
+        let url = spec.url;
+        std::thread::spawn(move || deliver_all(id, &url, retries, &rx));
+        let events = if spec.events.is_empty() {
+            DEFAULT_EVENTS
+                .iter()
+                .map(|&event| event.to_owned())
+                .collect()
+        } else {
+            spec.events
+        };
+        Self {
+            id,
+            events,
+            templates: spec.templates,
+            debounce: spec.debounce.unwrap_or(DEFAULT_DEBOUNCE),
+            pending: HashMap::new(),
+            payloads,
+        }
+    }
+
+    /// Combines the event with a pending change of the same server and
+    /// topic. Changes which cancel out are dropped
+    fn observe(&mut self, event: ServerEvent, now: Instant) {
+        let key = (event.server().to_owned(), topic(&event));
+        match self.pending.remove(&key) {
+            Some(pending) => {
+                if let Some(event) = merge(pending.event, event) {
+                    let due = pending.due;
+                    self.pending.insert(key, Pending { event, due });
+                }
+            },
+            None => {
+                let due = now + self.debounce;
+                self.pending.insert(key, Pending { event, due });
+            },
+        }
+    }
+
+    /// Queues payloads of changes which waited for the debounce time
+    fn flush(&mut self, now: Instant) {
+        let mut due = vec![];
+        self.pending.retain(|_, pending| {
+            let waiting = pending.due > now;
+            if !waiting {
+                due.push((pending.due, pending.event.clone()));
+            }
+            waiting
+        });
+        due.sort_by_key(|(due, _)| *due);
+        for (_, event) in due {
+            if !self.events.iter().any(|e| e == event.label()) {
+                continue;
+            }
+            let template = self.templates.get(event.label());
+            let payload = match render(&event, template.map(String::as_str)) {
+                Ok(payload) => payload,
+                Err(err) => {
+                    tracing::warn!("Can't render webhook payload: {}", err);
+                    continue;
+                },
+            };
+            match self.payloads.try_send(payload) {
+                Ok(()) => {},
+                Err(TrySendError::Full(_)) => {
+                    tracing::warn!(
+                        "Webhook {} is behind, event dropped",
+                        self.id
+                    );
+                },
+                Err(TrySendError::Disconnected(_)) => {
+                    tracing::warn!("Webhook {} has stopped", self.id);
+                },
+            }
+        }
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        self.pending.values().map(|pending| pending.due).min()
+    }
+}
+
+/// Sends events of game servers to webhooks from the config file
+pub struct Webhooks {
+    webhooks: Vec<Webhook>,
+}
+
+impl Webhooks {
+    /// Starts delivery threads, returns `None` without webhooks
+    pub fn start(specs: Vec<WebhookSpec>) -> Option<Self> {
+        if specs.is_empty() {
+            return None;
+        }
+        let webhooks = specs
+            .into_iter()
+            .enumerate()
+            .map(|(index, spec)| Webhook::start(index + 1, spec))
+            .collect();
+        Some(Self { webhooks })
+    }
+
+    pub async fn run(mut self, mut events: broadcast::Receiver<ServerEvent>) {
+        loop {
+            let next_due =
+                self.webhooks.iter().filter_map(Webhook::next_due).min();
+            let wait = next_due.map_or(Duration::ZERO, |due| {
+                due.saturating_duration_since(Instant::now())
+            });
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        let now = Instant::now();
+                        for webhook in &mut self.webhooks {
+                            webhook.observe(event.clone(), now);
+                        }
+                    },
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Webhooks skipped {} events", skipped);
+                    },
+                    Err(RecvError::Closed) => return,
+                },
+                () = time::sleep(wait), if next_due.is_some() => {},
+            }
+            let now = Instant::now();
+            for webhook in &mut self.webhooks {
+                webhook.flush(now);
+            }
+        }
+    }
+}
+
+/// Changes of the same topic of a server are combined
+fn topic(event: &ServerEvent) -> String {
+    match event {
+        ServerEvent::Up { .. } | ServerEvent::Down { .. } => "up".to_owned(),
+        ServerEvent::MapChange { .. } => "map".to_owned(),
+        ServerEvent::NameChange { .. } => "name".to_owned(),
+        ServerEvent::PlayerJoin { player, .. }
+        | ServerEvent::PlayerLeave { player, .. } => {
+            format!("player {player}")
+        },
+        ServerEvent::PlayersAbove { threshold, .. }
+        | ServerEvent::PlayersBelow { threshold, .. } => {
+            format!("players {threshold}")
+        },
+    }
+}
+
+/// Change from the state before the first event to the state after the
+/// next one, `None` if the state is the same
+fn merge(first: ServerEvent, next: ServerEvent) -> Option<ServerEvent> {
+    match (first, next) {
+        (
+            ServerEvent::MapChange { from, .. },
+            ServerEvent::MapChange { server, to, .. },
+        ) => {
+            (from != to).then_some(ServerEvent::MapChange { server, from, to })
+        },
+        (
+            ServerEvent::NameChange { from, .. },
+            ServerEvent::NameChange { server, to, .. },
+        ) => (from != to).then_some(ServerEvent::NameChange {
+            server,
+            from,
+            to,
+        }),
+        // Opposite events like down and up cancel out
+        (first, next) => (first.label() == next.label()).then_some(next),
+    }
+}
+
+/// Fills `{field}` placeholders of the template with JSON escaped fields of
+/// the event, the event is sent as JSON without a template
+fn render(
+    event: &ServerEvent,
+    template: Option<&str>,
+) -> serde_json::Result<String> {
+    let Some(template) = template else {
+        return serde_json::to_string(event);
+    };
+    let serde_json::Value::Object(fields) = serde_json::to_value(event)?
+    else {
+        return Ok(template.to_owned());
+    };
+    let mut payload = template.to_owned();
+    for (name, value) in fields {
+        let value = match value {
+            serde_json::Value::String(value) => {
+                let quoted = serde_json::to_string(&value)?;
+                quoted
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(&quoted)
+                    .to_owned()
+            },
+            value => value.to_string(),
+        };
+        payload = payload.replace(&format!("{{{name}}}"), &value);
+    }
+    Ok(payload)
+}
+
+/// Delivers payloads in order until the webhook is dropped
+fn deliver_all(id: usize, url: &Secret, retries: u32, rx: &Receiver<String>) {
+    let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+    while let Ok(payload) = rx.recv() {
+        if let Err(err) =
+            deliver(&agent, url.expose(), &payload, retries, INITIAL_BACKOFF)
+        {
+            tracing::warn!("Can't deliver webhook {}: {}", id, err);
+        }
+    }
+}
+
+/// Posts the payload, retrying server errors and failed connections with
+/// exponential backoff
+fn deliver(
+    agent: &ureq::Agent,
+    url: &str,
+    payload: &str,
+    retries: u32,
+    backoff: Duration,
+) -> anyhow::Result<()> {
+    let mut backoff = backoff;
+    let mut attempt = 0;
+    loop {
+        let result = agent
+            .post(url)
+            .set("Content-Type", "application/json")
+            .send_string(payload);
+        let err = match result {
+            Ok(_) => return Ok(()),
+            Err(ureq::Error::Status(status, _)) => {
+                let err = anyhow!("Webhook returned status {status}");
+                if status != 429 && status < 500 {
+                    return Err(err);
+                }
+                err
+            },
+            // Error of the transport is printed without the URL which may
+            // contain a token
+            Err(ureq::Error::Transport(transport)) => {
+                let message = transport.message().unwrap_or_default();
+                anyhow!("{}: {message}", transport.kind())
+            },
+        };
+        if attempt >= retries {
+            return Err(err);
+        }
+        tracing::debug!("Retrying webhook in {:?}: {}", backoff, err);
+        std::thread::sleep(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{deliver, merge, render, Webhook};
+    use crate::config::WebhookSpec;
+    use crate::events::ServerEvent;
+
+    fn map_change(from: &str, to: &str) -> ServerEvent {
+        ServerEvent::MapChange {
+            server: "127.0.0.1:27015".to_owned(),
+            from: from.to_owned(),
+            to: to.to_owned(),
+        }
+    }
+
+    fn down() -> ServerEvent {
+        ServerEvent::Down {
+            server: "127.0.0.1:27015".to_owned(),
+        }
+    }
+
+    fn up() -> ServerEvent {
+        ServerEvent::Up {
+            server: "127.0.0.1:27015".to_owned(),
+        }
+    }
+
+    #[test]
+    fn merge_server_events() {
+        assert_eq!(
+            merge(map_change("a", "b"), map_change("b", "c")),
+            Some(map_change("a", "c"))
+        );
+        assert_eq!(merge(map_change("a", "b"), map_change("b", "a")), None);
+        assert_eq!(merge(down(), up()), None);
+        assert_eq!(merge(down(), down()), Some(down()));
+    }
+
+    #[test]
+    fn debounce_flapping_server() {
+        let spec: WebhookSpec = toml::from_str(
+            "url = \"http://127.0.0.1:1/\"\nretries = 0\ndebounce = 10",
+        )
+        .unwrap();
+        let mut webhook = Webhook::start(1, spec);
+        let start = Instant::now();
+        webhook.observe(down(), start);
+        webhook.observe(up(), start + Duration::from_secs(2));
+        assert_eq!(webhook.next_due(), None);
+
+        webhook.observe(down(), start + Duration::from_secs(3));
+        webhook.observe(map_change("a", "b"), start + Duration::from_secs(4));
+        webhook.flush(start + Duration::from_secs(12));
+        assert_eq!(webhook.next_due(), Some(start + Duration::from_secs(13)));
+        webhook.flush(start + Duration::from_secs(13));
+        assert_eq!(webhook.next_due(), Some(start + Duration::from_secs(14)));
+    }
+
+    #[test]
+    fn render_template() {
+        let event = map_change("de_dust2", "de_\"inferno\"");
+        assert_eq!(
+            render(&event, Some("{\"text\": \"{server}: {from} -> {to}\"}"))
+                .unwrap(),
+            "{\"text\": \"127.0.0.1:27015: de_dust2 -> de_\\\"inferno\\\"\"}"
+        );
+        let event = ServerEvent::PlayersAbove {
+            server: "cs.example.com:27015".to_owned(),
+            threshold: 10,
+            players: 11,
+        };
+        assert_eq!(
+            render(&event, Some("{type} {players}/{threshold} {unknown}"))
+                .unwrap(),
+            "players_above 11/10 {unknown}"
+        );
+        assert_eq!(
+            render(&up(), None).unwrap(),
+            "{\"type\":\"up\",\"server\":\"127.0.0.1:27015\"}"
+        );
+    }
+
+    #[test]
+    fn retry_webhook_delivery() {
+        let receiver = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", receiver.server_addr());
+        let handle = std::thread::spawn(move || {
+            let mut bodies = vec![];
+            for status in [503, 200, 400] {
+                let mut request = receiver.recv().unwrap();
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                bodies.push(body);
+                request.respond(tiny_http::Response::empty(status)).unwrap();
+            }
+            bodies
+        });
+
+        let agent = ureq::agent();
+        let backoff = Duration::from_millis(10);
+        assert!(deliver(&agent, &url, "{}", 1, backoff).is_ok());
+        // Client errors are not retried
+        assert!(deliver(&agent, &url, "[]", 1, backoff).is_err());
+        assert_eq!(handle.join().unwrap(), ["{}", "{}", "[]"]);
+    }
+}